@@ -0,0 +1,594 @@
+use lockmousetomonitor::margin::EdgeMargin;
+use lockmousetomonitor::{Insets, LockAxis, LockShape, MonitorOrder};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::{fmt, str::FromStr};
+
+/// Which monitor to lock to, as given with `--monitor` or in the config.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum MonitorSelector {
+    /// 1-based monitor number, matching the numbers shown in the listing.
+    Number(usize),
+    /// Several 1-based monitor numbers, locked to as one area.
+    Group(Vec<usize>),
+    /// Every monitor as one area, which only keeps the cursor out of the
+    /// gaps of a layout that isn't a rectangle.
+    All,
+    /// Every monitor but this 1-based one, written `exclude:2`: keeps the
+    /// cursor off a monitor rather than on one.
+    Exclude(usize),
+    /// Whichever monitor the cursor is on at startup.
+    Current,
+    /// The primary monitor.
+    Primary,
+    /// The monitor the foreground window is on at startup, falling back to
+    /// the cursor's.
+    Foreground,
+    /// The monitor a fullscreen window covers at startup, the topmost one if
+    /// there are several, falling back to the cursor's.
+    Fullscreen,
+    /// The monitor with this resolution, like `2560x1440`.
+    Resolution { width: i32, height: i32 },
+    /// The monitor whose top-left corner is here, like `at:0,0`. Unlike the
+    /// numbers these don't shift when displays are rearranged.
+    Position { x: i32, y: i32 },
+    /// The monitor with this GDI device name (`\\.\DISPLAY2`) or device path,
+    /// both as shown by `--list`, written `device:...`. The path survives
+    /// reboots and rearranging.
+    Device(String),
+}
+
+#[derive(Default)]
+pub struct Args {
+    pub monitor: Option<MonitorSelector>,
+    /// `--rect`: left, top, right and bottom in virtual-desktop coordinates,
+    /// locked to instead of a monitor.
+    pub rect: Option<[i32; 4]>,
+    /// Lock to the monitor's work area, leaving the taskbar reachable.
+    pub work_area: bool,
+    pub edge_margin: Option<EdgeMargin>,
+    /// How far into the monitor a released cursor must come to be re-locked.
+    pub relock_inset: Option<EdgeMargin>,
+    pub lock_axis: Option<LockAxis>,
+    /// How the monitors are numbered.
+    pub monitor_order: Option<MonitorOrder>,
+    /// Hold the cursor to an ellipse or circle instead of the whole rect.
+    pub shape: Option<LockShape>,
+    /// Name of a config profile to start with.
+    pub profile: Option<String>,
+    /// Per-edge insets; unset edges fall back to the config.
+    pub inset_left: Option<i32>,
+    pub inset_top: Option<i32>,
+    pub inset_right: Option<i32>,
+    pub inset_bottom: Option<i32>,
+    /// Accept commands on a named pipe.
+    pub pipe: bool,
+    /// Address for the HTTP control endpoint.
+    pub http: Option<SocketAddr>,
+    /// Free the cursor only while a release key is held.
+    pub hold_release: bool,
+    /// Only arm the release on two quick presses of a release key.
+    pub double_tap_release: bool,
+    /// Seconds after an edge release to bring the cursor back.
+    pub relock_after: Option<u64>,
+    /// Seconds without cursor movement before the lock lets go.
+    pub idle_release: Option<u64>,
+    /// Seconds the timed-release hotkey frees the cursor for.
+    pub timed_release: Option<u64>,
+    /// Warp the cursor back each poll instead of using `ClipCursor`.
+    pub soft_lock: bool,
+    /// Glide the cursor back when it's warped instead of jumping.
+    pub smooth_warp: bool,
+    /// Read keys through a low-level keyboard hook instead of polling.
+    pub input_hook: bool,
+    /// Clip to the foreground window instead of a monitor.
+    pub lock_window: bool,
+    /// Warp the cursor to the middle of the monitor when locking to it.
+    pub center_on_switch: bool,
+    /// Warp the cursor to the middle of a new lock it's outside of.
+    pub warp_in: bool,
+    /// Move the lock to whichever monitor a fullscreen window takes over.
+    pub follow_fullscreen: bool,
+    /// Open the monitor edge a window is dragged against until the button
+    /// is released.
+    pub drag_widen: bool,
+    /// Re-apply the clip and warp the cursor back on every poll while a
+    /// fullscreen window is on the locked monitor.
+    pub game_mode: bool,
+    /// Print the monitors as JSON and exit.
+    pub list: bool,
+    /// Show each monitor's number on it for a moment and exit.
+    pub identify: bool,
+    /// Drop monitors that mirror another from the listing and numbering.
+    pub no_duplicates: bool,
+    /// Detach from the console after startup and log to a file instead.
+    pub background: bool,
+    /// Start locked from the saved settings without any output or prompts.
+    /// Implies `background`.
+    pub daemon: bool,
+    /// Lock to the current monitor without a prompt, printing only errors.
+    pub quiet: bool,
+    /// Exit at the first edge release instead of re-locking.
+    pub once: bool,
+    /// Run under the Service Control Manager, locking in the console session.
+    pub service: bool,
+    /// With `service`, also lock the sign-in screen while nobody is logged in.
+    pub logon_screen: bool,
+    /// Seconds to wait before looking at the monitors.
+    pub delay: Option<u64>,
+    /// Echo state changes to the console as well as the log file.
+    pub verbose: bool,
+    /// Show notifications when the lock changes.
+    pub toast: bool,
+    /// Draw a border around the locked monitor.
+    pub overlay: bool,
+    /// Beep when the clip engages or releases.
+    pub sound: bool,
+    /// Individual `sound` cues, switched off with `--no-lock-sound` and
+    /// `--no-release-sound`.
+    pub lock_sound: Option<bool>,
+    pub release_sound: Option<bool>,
+    /// Print `EVENT ...` lines on stdout for scripts.
+    pub events: bool,
+    /// Resolve the monitor and rect, print them and exit without clipping.
+    pub dry_run: bool,
+    /// Print the usage text and exit.
+    pub help: bool,
+    /// Print the version and exit.
+    pub version: bool,
+}
+
+/// The `--help` text. The controls list the default keys; the config can
+/// change them.
+pub const USAGE: &str = "\
+Usage: lockmousetomonitor [options]
+
+Keeps the mouse cursor locked to one monitor. Without --monitor it lists
+the monitors and asks which one to use, unless one is saved in config.toml.
+
+Monitor:
+  --monitor N           Lock to monitor N; also 1,2 / current / primary /
+                        foreground / WxH (a resolution) / at:X,Y (a position)
+                        / device:NAME (a device name or path from --list)
+                        / fullscreen / all
+  --lock-all            Same as --monitor all: keep the cursor on the desktop,
+                        out of the gaps between mismatched monitors
+  --exclude N           Keep the cursor off monitor N instead of on one
+                        (same as --monitor exclude:N)
+  --rect L,T,R,B        Lock to this rect in desktop coordinates instead of a
+                        monitor
+  --profile NAME        Start with a profile from the config
+  --list                Print the monitors as JSON and exit
+  --no-duplicates       Leave out monitors that mirror another one
+  --order left|top|name|system
+                        Number the monitors left to right (the default), top
+                        to bottom, by device name, or as Windows lists them
+  --identify            Show each monitor's number on it for a moment and exit
+  --dry-run             Print the rect and mode the other options lead to,
+                        then exit without locking
+  --lock-window         Lock to the foreground window instead of a monitor
+  --follow-fullscreen   Move the lock to a monitor a fullscreen window takes over
+  --center-on-switch    Move the cursor to the middle of a newly locked monitor
+  --warp-in             Move the cursor into a newly locked monitor it's not on
+
+Lock:
+  --work-area           Keep the taskbar out of bounds
+  --soft-lock           Move the cursor back instead of using ClipCursor
+  --drag-widen          Open an edge while a window is dragged against it
+  --game-mode           Hold the cursor on every check while a fullscreen
+                        window is on the locked monitor
+  --smooth-warp         Glide the cursor back in a few steps instead of jumping
+  --lock-axis x|y       Only hold the cursor horizontally or vertically
+  --shape ellipse|circle
+                        Hold the cursor to the ellipse or circle inside the
+                        monitor instead of the whole rect
+  --inset-left N, --inset-top N, --inset-right N, --inset-bottom N
+                        Keep the cursor N pixels from that edge
+  --edge-margin N|N%    How close to the edge counts as the edge (default 1)
+  --relock-inset N|N%   How far inside a released cursor must come to re-lock
+
+Release:
+  --hold-release        Free the cursor only while a release key is held
+  --double-tap-release  Arm the release on two quick presses
+  --relock-after SECS   Bring the cursor back if it hasn't returned in time
+  --idle-release SECS   Let the cursor go after SECS without movement
+  --timed-release SECS  Make F10 free the cursor for SECS, then re-lock
+  --input-hook          Read keys through a low-level keyboard hook
+
+Running:
+  --background          Detach from the console after startup
+  --once                Exit the first time the edge release lets the cursor go
+  --delay SECS          Wait SECS for the displays to settle before locking
+  --daemon              Lock to the saved monitor silently, in the background
+  --service             Run as a Windows service (see the README for setup)
+  --logon-screen        With --service, also lock the sign-in screen
+  --pipe                Accept commands on \\\\.\\pipe\\LockMouseToMonitor
+  --http ADDR           Accept commands over HTTP on ADDR (a bare port means
+                        127.0.0.1)
+  --events              Print EVENT lines on stdout for scripts
+  --toast               Show a notification when the lock changes
+  --overlay             Draw a border around the locked monitor
+  --sound               Beep when the clip engages and releases
+  --no-lock-sound, --no-release-sound
+                        Leave out one of the --sound cues
+  -q, --quiet           Lock to the current monitor without a prompt and
+                        print only errors
+  -v, --verbose         Print each lock, release and switch as it happens
+  -h, --help            Print this help and exit
+  -V, --version         Print the version and exit
+
+Controls:
+  Ctrl or left Alt      Release the lock when the cursor next reaches the edge
+  F11                   Lock to the monitor the cursor is on
+  Ctrl+F11              Free the cursor, then lock to the monitor it stops on
+  Ctrl+Alt+Right/Left   Move the lock to the next / previous monitor
+  Ctrl+Alt+L            Turn the lock off or back on
+  Ctrl+Alt+P            Switch to the next profile
+  Ctrl+Alt+R            Reload the config file
+  Ctrl+Alt+S            Print the locked monitor, rect and mode
+  Ctrl+Alt+F            Swap the lock between the two favorite monitors
+  Ctrl+Alt+Numpad1-9    Lock to that monitor
+  F10                   Free the cursor for a few seconds (with --timed-release)
+  Ctrl+C                Release the cursor and exit
+";
+
+impl Args {
+    /// Whether to keep the console quiet: `--daemon` or `--quiet`.
+    pub fn silent(&self) -> bool {
+        self.daemon || self.quiet
+    }
+
+    pub fn parse() -> Result<Args, String> {
+        Args::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut args = Args::default();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--monitor" => {
+                    let value = iter.next().ok_or("--monitor needs a value (a monitor number, a resolution, at:x,y, \"current\", \"primary\", \"foreground\" or \"fullscreen\")")?;
+                    args.monitor = Some(value.parse()?);
+                }
+                "--lock-all" => args.monitor = Some(MonitorSelector::All),
+                "--exclude" => {
+                    let value = iter.next().ok_or("--exclude needs a monitor number")?;
+                    args.monitor = Some(parse_monitor_selector(&format!("exclude:{}", value.trim()))?);
+                }
+                "--rect" => {
+                    let value = iter.next().ok_or("--rect needs left,top,right,bottom")?;
+                    args.rect = Some(parse_rect(&value)?);
+                }
+                "--help" | "-h" => args.help = true,
+                "--version" | "-V" => args.version = true,
+                "--list" | "--list-json" => args.list = true,
+                "--no-duplicates" => args.no_duplicates = true,
+                "--identify" => args.identify = true,
+                "--dry-run" => args.dry_run = true,
+                "--background" | "--hidden" => args.background = true,
+                "--daemon" => {
+                    args.daemon = true;
+                    args.background = true;
+                }
+                "--quiet" | "-q" => args.quiet = true,
+                "--once" => args.once = true,
+                "--service" => args.service = true,
+                "--logon-screen" => args.logon_screen = true,
+                "--delay" => {
+                    let value = iter.next().ok_or("--delay needs a number of seconds")?;
+                    let secs = value.parse().map_err(|_| format!("Invalid --delay \"{}\": expected a number of seconds", value))?;
+                    args.delay = Some(secs);
+                }
+                "--verbose" | "-v" => args.verbose = true,
+                "--events" => args.events = true,
+                "--sound" => args.sound = true,
+                "--toast" => args.toast = true,
+                "--overlay" => args.overlay = true,
+                "--no-lock-sound" => args.lock_sound = Some(false),
+                "--no-release-sound" => args.release_sound = Some(false),
+                "--work-area" => args.work_area = true,
+                "--soft-lock" => args.soft_lock = true,
+                "--smooth-warp" => args.smooth_warp = true,
+                "--lock-window" => args.lock_window = true,
+                "--center-on-switch" => args.center_on_switch = true,
+                "--warp-in" => args.warp_in = true,
+                "--follow-fullscreen" => args.follow_fullscreen = true,
+                "--game-mode" => args.game_mode = true,
+                "--drag-widen" => args.drag_widen = true,
+                "--double-tap-release" => args.double_tap_release = true,
+                "--hold-release" => args.hold_release = true,
+                "--pipe" => args.pipe = true,
+                "--http" => {
+                    let value = iter.next().ok_or("--http needs an address to listen on, like 127.0.0.1:8787")?;
+                    args.http = Some(parse_http_addr(&value)?);
+                }
+                "--input-hook" => args.input_hook = true,
+                "--relock-after" => {
+                    let value = iter.next().ok_or("--relock-after needs a number of seconds")?;
+                    let secs = value.parse().map_err(|_| format!("Invalid --relock-after \"{}\": expected a number of seconds", value))?;
+                    args.relock_after = Some(secs);
+                }
+                "--idle-release" => {
+                    let value = iter.next().ok_or("--idle-release needs a number of seconds")?;
+                    let secs = value.parse().map_err(|_| format!("Invalid --idle-release \"{}\": expected a number of seconds", value))?;
+                    args.idle_release = Some(secs);
+                }
+                "--timed-release" => {
+                    let value = iter.next().ok_or("--timed-release needs a number of seconds")?;
+                    let secs = value.parse().map_err(|_| format!("Invalid --timed-release \"{}\": expected a number of seconds", value))?;
+                    args.timed_release = Some(secs);
+                }
+                "--inset-left" | "--inset-top" | "--inset-right" | "--inset-bottom" => {
+                    let value = iter.next().ok_or_else(|| format!("{} needs a number of pixels", arg))?;
+                    let px: i32 = value.parse()
+                        .map_err(|_| format!("Invalid {} \"{}\": expected a number of pixels", arg, value))?;
+                    if px < 0 {
+                        return Err(format!("Invalid {} {}: must not be negative", arg, px));
+                    }
+                    let slot = match arg.as_str() {
+                        "--inset-left" => &mut args.inset_left,
+                        "--inset-top" => &mut args.inset_top,
+                        "--inset-right" => &mut args.inset_right,
+                        _ => &mut args.inset_bottom,
+                    };
+                    *slot = Some(px);
+                }
+                "--profile" => {
+                    let value = iter.next().ok_or("--profile needs a profile name")?;
+                    args.profile = Some(value);
+                }
+                "--shape" => {
+                    let value = iter.next().ok_or("--shape needs a value (rect, ellipse or circle)")?;
+                    args.shape = Some(value.parse()?);
+                }
+                "--order" => {
+                    let value = iter.next().ok_or("--order needs a value (left, top, name or system)")?;
+                    args.monitor_order = Some(value.parse()?);
+                }
+                "--lock-axis" => {
+                    let value = iter.next().ok_or("--lock-axis needs a value (x or y)")?;
+                    args.lock_axis = Some(value.parse()?);
+                }
+                "--relock-inset" => {
+                    let value = iter.next().ok_or("--relock-inset needs a value (pixels, or a percentage like 2%)")?;
+                    args.relock_inset = Some(value.parse()?);
+                }
+                "--edge-margin" => {
+                    let value = iter.next().ok_or("--edge-margin needs a value (pixels, or a percentage like 1%)")?;
+                    args.edge_margin = Some(value.parse()?);
+                }
+                _ => return Err(format!("Unknown argument: {} (see --help)", arg)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// `127.0.0.1:8787`, or a bare port for localhost.
+fn parse_http_addr(value: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = value.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    value.parse().map_err(|_| format!("Invalid --http address \"{}\": expected something like 127.0.0.1:8787", value))
+}
+
+/// `left,top,right,bottom`, which has to have some width and height.
+fn parse_rect(value: &str) -> Result<[i32; 4], String> {
+    let invalid = || format!("Invalid --rect \"{}\": expected left,top,right,bottom", value);
+    let parts: Vec<i32> = value.split(',').map(|part| part.trim().parse()).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let [left, top, right, bottom] = parts[..] else {
+        return Err(invalid());
+    };
+    if right <= left || bottom <= top {
+        return Err(format!("Invalid --rect \"{}\": right must be greater than left and bottom greater than top", value));
+    }
+    Ok([left, top, right, bottom])
+}
+
+/// `value` without `prefix`, matched regardless of case like the keywords.
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &value[prefix.len()..])
+}
+
+fn parse_pair(value: &str, separator: char) -> Option<(i32, i32)> {
+    let (a, b) = value.split_once(separator)?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+impl FromStr for MonitorSelector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_monitor_selector(value.trim())
+    }
+}
+
+fn parse_monitor_selector(value: &str) -> Result<MonitorSelector, String> {
+    let invalid = || format!(
+        "Invalid monitor \"{}\": expected a monitor number, a list like 1,2, a resolution like 2560x1440, a position like at:0,0, device:<name or path>, exclude:<number>, \"current\", \"primary\", \"foreground\", \"fullscreen\" or \"all\"",
+        value);
+    if value.eq_ignore_ascii_case("all") {
+        return Ok(MonitorSelector::All);
+    }
+    if value.eq_ignore_ascii_case("current") {
+        return Ok(MonitorSelector::Current);
+    }
+    if value.eq_ignore_ascii_case("primary") {
+        return Ok(MonitorSelector::Primary);
+    }
+    if value.eq_ignore_ascii_case("foreground") {
+        return Ok(MonitorSelector::Foreground);
+    }
+    if value.eq_ignore_ascii_case("fullscreen") {
+        return Ok(MonitorSelector::Fullscreen);
+    }
+    if let Some(number) = strip_prefix_ignore_case(value, "exclude:") {
+        return match number.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(MonitorSelector::Exclude(n)),
+            _ => Err(invalid()),
+        };
+    }
+    if let Some(device) = strip_prefix_ignore_case(value, "device:") {
+        return match device.trim() {
+            "" => Err(invalid()),
+            device => Ok(MonitorSelector::Device(device.to_string())),
+        };
+    }
+    if let Some(position) = strip_prefix_ignore_case(value, "at:") {
+        let (x, y) = parse_pair(position, ',').ok_or_else(invalid)?;
+        return Ok(MonitorSelector::Position { x, y });
+    }
+    if value.contains(['x', 'X']) {
+        return match parse_pair(&value.to_ascii_lowercase(), 'x') {
+            Some((width, height)) if width > 0 && height > 0 => Ok(MonitorSelector::Resolution { width, height }),
+            _ => Err(invalid()),
+        };
+    }
+    let mut numbers = Vec::new();
+    for part in value.split(',') {
+        match part.trim().parse::<usize>() {
+            // A repeat anywhere in the list is the same monitor again
+            Ok(n) if n > 0 => {
+                if !numbers.contains(&n) {
+                    numbers.push(n);
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if numbers.len() == 1 {
+        Ok(MonitorSelector::Number(numbers[0]))
+    } else {
+        Ok(MonitorSelector::Group(numbers))
+    }
+}
+
+impl Args {
+    /// The insets from the command line, with `config` filling in the edges
+    /// that weren't given.
+    pub fn insets(&self, config: Insets) -> Insets {
+        Insets {
+            left: self.inset_left.unwrap_or(config.left),
+            top: self.inset_top.unwrap_or(config.top),
+            right: self.inset_right.unwrap_or(config.right),
+            bottom: self.inset_bottom.unwrap_or(config.bottom),
+        }
+    }
+}
+
+impl fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonitorSelector::Number(n) => write!(f, "{}", n),
+            MonitorSelector::Group(numbers) => {
+                let numbers: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+                write!(f, "{}", numbers.join(","))
+            }
+            MonitorSelector::All => write!(f, "all"),
+            MonitorSelector::Exclude(n) => write!(f, "exclude:{}", n),
+            MonitorSelector::Current => write!(f, "current"),
+            MonitorSelector::Primary => write!(f, "primary"),
+            MonitorSelector::Foreground => write!(f, "foreground"),
+            MonitorSelector::Fullscreen => write!(f, "fullscreen"),
+            MonitorSelector::Resolution { width, height } => write!(f, "{}x{}", width, height),
+            MonitorSelector::Position { x, y } => write!(f, "at:{},{}", x, y),
+            MonitorSelector::Device(device) => write!(f, "device:{}", device),
+        }
+    }
+}
+
+impl TryFrom<String> for MonitorSelector {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<MonitorSelector> for String {
+    fn from(selector: MonitorSelector) -> String {
+        selector.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Result<MonitorSelector, String> {
+        value.parse()
+    }
+
+    #[test]
+    fn parses_numbers_and_groups() {
+        assert_eq!(parse("2"), Ok(MonitorSelector::Number(2)));
+        assert_eq!(parse(" 1, 3 "), Ok(MonitorSelector::Group(vec![1, 3])));
+        // Repeats collapse to a single monitor
+        assert_eq!(parse("2,2"), Ok(MonitorSelector::Number(2)));
+        assert!(parse("0").is_err());
+        assert!(parse("1,").is_err());
+        assert!(parse("-1").is_err());
+    }
+
+    #[test]
+    fn repeats_apart_collapse_in_first_seen_order() {
+        assert_eq!(parse("1,2,1"), Ok(MonitorSelector::Group(vec![1, 2])));
+        assert_eq!(parse("3,1,3,2,1"), Ok(MonitorSelector::Group(vec![3, 1, 2])));
+        assert_eq!(parse("2,1,2"), Ok(MonitorSelector::Group(vec![2, 1])));
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(parse("all"), Ok(MonitorSelector::All));
+        assert_eq!(parse("Current"), Ok(MonitorSelector::Current));
+        assert_eq!(parse("PRIMARY"), Ok(MonitorSelector::Primary));
+        assert_eq!(parse("foreground"), Ok(MonitorSelector::Foreground));
+        assert_eq!(parse("fullscreen"), Ok(MonitorSelector::Fullscreen));
+    }
+
+    #[test]
+    fn parses_resolution_and_negative_position() {
+        assert_eq!(parse("2560X1440"), Ok(MonitorSelector::Resolution { width: 2560, height: 1440 }));
+        assert_eq!(parse("at:-1080,-420"), Ok(MonitorSelector::Position { x: -1080, y: -420 }));
+        assert!(parse("0x1080").is_err());
+        assert!(parse("at:5").is_err());
+    }
+
+    #[test]
+    fn parses_device() {
+        assert_eq!(parse(r"device:\\.\DISPLAY2"), Ok(MonitorSelector::Device(r"\\.\DISPLAY2".to_string())));
+        assert!(parse("device:").is_err());
+    }
+
+    #[test]
+    fn prefixes_ignore_case() {
+        assert_eq!(parse("Exclude:2"), Ok(MonitorSelector::Exclude(2)));
+        assert_eq!(parse(r"Device:\\.\DISPLAY2"), Ok(MonitorSelector::Device(r"\\.\DISPLAY2".to_string())));
+        assert_eq!(parse("AT:0,0"), Ok(MonitorSelector::Position { x: 0, y: 0 }));
+    }
+
+    #[test]
+    fn parses_exclude() {
+        assert_eq!(parse("exclude:2"), Ok(MonitorSelector::Exclude(2)));
+        assert!(parse("exclude:0").is_err());
+        assert!(parse("exclude:").is_err());
+    }
+
+    #[test]
+    fn parses_rect() {
+        assert_eq!(parse_rect("0, 0, 960, 540"), Ok([0, 0, 960, 540]));
+        assert_eq!(parse_rect("-1920,0,0,1080"), Ok([-1920, 0, 0, 1080]));
+        assert!(parse_rect("0,0,960").is_err());
+        assert!(parse_rect("100,0,100,540").is_err());
+        assert!(parse_rect("0,540,960,0").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for value in ["3", "1,2", "all", "exclude:2", "primary", "1920x1080", "at:-1920,0", r"device:\\.\DISPLAY1"] {
+            assert_eq!(parse(value).unwrap().to_string(), value);
+        }
+    }
+}
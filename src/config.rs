@@ -0,0 +1,262 @@
+use crate::args::MonitorSelector;
+use crate::hotkey::{Hotkey, Key, Modifiers};
+use lockmousetomonitor::margin::EdgeMargin;
+use lockmousetomonitor::{Insets, LockAxis, LockShape, MonitorLocker, MonitorOrder, MonitorOverride};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fs, io, path::PathBuf};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// Points at a config file to use instead of the one next to the executable.
+const CONFIG_ENV_VAR: &str = "LOCKMOUSE_CONFIG";
+
+/// Set when [`Config::load`] found a file it couldn't parse. [`Config::save`]
+/// then leaves the file alone, so a typo doesn't get the user's settings
+/// overwritten with the defaults, until a [`Config::reload`] succeeds.
+static UNPARSED: AtomicBool = AtomicBool::new(false);
+
+/// Settings persisted between runs in `config.toml` next to the executable.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Last locked monitor, 1-based to match the numbers shown in the listing.
+    pub monitor: Option<usize>,
+    /// Lock straight to `monitor` on startup instead of asking.
+    pub skip_prompt: bool,
+    /// Seconds to wait at startup before the monitors are enumerated, for a
+    /// display layout that's still settling after login.
+    pub startup_delay: Option<u64>,
+    /// Picks the monitor on startup by resolution (`"2560x1440"`) or position
+    /// (`"at:0,0"`), taking precedence over `monitor`. Takes the same values
+    /// as `--monitor`.
+    pub monitor_selector: Option<MonitorSelector>,
+    /// How monitors are numbered: `"left"` (the default), `"top"`, `"name"`
+    /// or `"system"`. `monitor` is a number in this order.
+    pub monitor_order: Option<MonitorOrder>,
+    /// Lock to the work area (excluding the taskbar) instead of the full monitor.
+    pub work_area: bool,
+    /// Confine by warping the cursor back instead of with `ClipCursor`.
+    pub soft_lock: bool,
+    /// Glide the cursor back over a few steps when it's warped.
+    pub smooth_warp: bool,
+    /// Clip to the foreground window, following it as focus changes.
+    pub lock_window: bool,
+    /// Warp the cursor to the middle of the monitor on the initial lock and F11.
+    pub center_on_switch: bool,
+    /// Warp the cursor to the middle of a new lock it started outside of.
+    pub warp_in: bool,
+    /// Move the lock to the monitor a fullscreen foreground window is on.
+    pub follow_fullscreen: bool,
+    /// Open the edge a window is dragged against until the button is released.
+    pub drag_widen: bool,
+    /// Clip and warp on every poll while a fullscreen window is on the
+    /// locked monitor.
+    pub game_mode: bool,
+    /// Show a notification when the lock engages, releases or switches.
+    pub toast: bool,
+    /// Draw a border around the locked monitor while the clip is on.
+    pub overlay: bool,
+    /// Beep when the clip engages and when it releases.
+    pub sound: bool,
+    /// Turn off one of the `sound` cues with `false`.
+    pub lock_sound: Option<bool>,
+    pub release_sound: Option<bool>,
+    /// Accept `LOCK n`/`RELEASE`/`TOGGLE`/`STATUS` on a named pipe.
+    pub pipe: bool,
+    /// Serve the HTTP control endpoint on this address, like
+    /// `"127.0.0.1:8787"`.
+    pub http: Option<SocketAddr>,
+    /// Take the release keys and hotkeys from a `WH_KEYBOARD_LL` hook.
+    pub input_hook: bool,
+    /// Pixels kept clear along each monitor edge, as an `[insets]` table.
+    pub insets: Insets,
+    /// Process names (`"explorer.exe"`) or window class names that suspend the
+    /// lock while they have focus.
+    pub pause_for: Vec<String>,
+    /// Distance from the monitor edge that releases an armed lock.
+    pub edge_margin: EdgeMargin,
+    /// Distance past the monitor edge a released cursor has to come back
+    /// before it's re-locked.
+    pub relock_inset: Option<EdgeMargin>,
+    /// Hold the cursor horizontally (`"x"`) or vertically (`"y"`) only.
+    pub lock_axis: LockAxis,
+    /// `"ellipse"` or `"circle"` to hold the cursor to that shape inside the
+    /// monitor.
+    pub shape: LockShape,
+    /// Free the cursor while a release key is held rather than arming the
+    /// edge release.
+    pub hold_release: bool,
+    /// Arm the release on a double tap of a release key rather than a single
+    /// press, which Ctrl shortcuts set off by accident.
+    pub double_tap_release: bool,
+    /// Longest gap between the two taps, in milliseconds (default 400).
+    pub double_tap_window_ms: Option<u64>,
+    /// Seconds after an edge release before the cursor is brought back to the
+    /// locked monitor. Off when unset or 0.
+    pub relock_after: Option<u64>,
+    /// Seconds without any cursor movement before the lock is released until
+    /// the mouse moves again. Off when unset or 0.
+    pub idle_release: Option<u64>,
+    /// Seconds `timed_release_hotkey` frees the cursor for before it's locked
+    /// again. Off when unset or 0.
+    pub timed_release: Option<u64>,
+    /// Milliseconds between checks while the mouse or keys are in use
+    /// (default 15).
+    pub poll_interval_ms: Option<u32>,
+    /// Milliseconds between checks after a few seconds without activity
+    /// (default 100). Set it to `poll_interval_ms` to always poll fast.
+    pub idle_poll_interval_ms: Option<u32>,
+    /// Hotkey that locks to the monitor under the cursor (default `"F11"`),
+    /// e.g. `"Ctrl+Shift+F11"` to leave F11 to browsers.
+    pub switch_hotkey: Option<Hotkey>,
+    /// Hotkey that turns the lock on and off, like `"Ctrl+Alt+L"`.
+    pub toggle_hotkey: Option<Hotkey>,
+    /// Keys that arm the edge release, by name or virtual-key code, e.g.
+    /// `["RCtrl"]` for right Ctrl only. Defaults to either Ctrl or the left Alt.
+    pub release_keys: Option<Vec<Key>>,
+    /// Hotkeys that move the lock along the monitor list.
+    pub next_monitor_hotkey: Option<Hotkey>,
+    pub prev_monitor_hotkey: Option<Hotkey>,
+    /// Named setups, as `[profiles.<name>]` tables, picked with `--profile`
+    /// or cycled through (in name order) with `profile_hotkey`.
+    pub profiles: BTreeMap<String, Profile>,
+    pub profile_hotkey: Option<Hotkey>,
+    /// Two monitor numbers, like `[1, 3]`, that `favorite_hotkey` swaps the
+    /// lock between.
+    pub favorite_monitors: Option<[usize; 2]>,
+    pub favorite_hotkey: Option<Hotkey>,
+    /// Modifiers that go with Numpad1-Numpad9 to lock straight to monitors
+    /// 1-9 (default `"Ctrl+Alt"`). An empty string turns them off.
+    pub jump_modifiers: Option<Modifiers>,
+    /// `work_area`, `edge_margin` and `insets` for single monitors, as
+    /// `[overrides.'\\.\DISPLAY1']` tables keyed by device name.
+    pub overrides: BTreeMap<String, MonitorOverride>,
+    /// Hotkey that re-reads this file and applies it (default `"Ctrl+Alt+R"`).
+    pub reload_hotkey: Option<Hotkey>,
+    /// Hotkey for `timed_release` (default `"F10"`).
+    pub timed_release_hotkey: Option<Hotkey>,
+    /// Hotkey that releases the lock and moves it to the monitor the cursor
+    /// ends up on (default `"Ctrl+F11"`).
+    pub pick_monitor_hotkey: Option<Hotkey>,
+    /// Hotkey that prints the state, as `STATUS` on the pipe reports it
+    /// (default `"Ctrl+Alt+S"`).
+    pub status_hotkey: Option<Hotkey>,
+}
+
+/// A named lock setup. Every setting is captured, so switching profiles never
+/// leaves a setting from the previous one behind; unset ones take their
+/// defaults.
+#[derive(Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Profile {
+    /// Which monitors to lock to; keeps the current ones when unset.
+    pub monitor: Option<MonitorSelector>,
+    pub work_area: bool,
+    pub soft_lock: bool,
+    pub lock_axis: LockAxis,
+    pub edge_margin: EdgeMargin,
+    pub insets: Insets,
+}
+
+impl Profile {
+    /// Applies everything but the monitor, which takes effect with the next
+    /// lock.
+    pub fn apply_settings(&self, locker: &mut MonitorLocker) -> Result<(), String> {
+        self.insets.validate()?;
+        locker.set_work_area(self.work_area);
+        locker.set_soft_lock(self.soft_lock);
+        locker.set_lock_axis(self.lock_axis);
+        locker.set_edge_margin(self.edge_margin);
+        locker.set_insets(self.insets);
+        Ok(())
+    }
+}
+
+fn env_config_path() -> Option<PathBuf> {
+    std::env::var_os(CONFIG_ENV_VAR).filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env_config_path() {
+        return Some(path);
+    }
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(CONFIG_FILE_NAME))
+}
+
+impl Config {
+    /// Reads the config file. Returns `None` if it is missing or can't be parsed.
+    pub fn load() -> Option<Config> {
+        let text = fs::read_to_string(config_path()?).ok()?;
+        let config = toml::from_str(&text).ok();
+        UNPARSED.store(config.is_none(), Ordering::SeqCst);
+        config
+    }
+
+    /// What's wrong with the file `LOCKMOUSE_CONFIG` points at, if it's set
+    /// and the file can't be read.
+    pub fn env_path_warning() -> Option<String> {
+        let path = env_config_path()?;
+        match fs::File::open(&path) {
+            Ok(_) if path.is_dir() => Some(format!("{} {} is a directory, not a config file", CONFIG_ENV_VAR, path.display())),
+            Ok(_) => None,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Some(format!(
+                "{} points to {}, which doesn't exist; using the defaults, and it's created when a monitor is saved",
+                CONFIG_ENV_VAR, path.display()
+            )),
+            Err(e) => Some(format!("{} {} can't be read: {}", CONFIG_ENV_VAR, path.display(), e)),
+        }
+    }
+
+    /// Why the config file was skipped, if it's there but doesn't parse, such
+    /// as an unknown key name in `release_keys`.
+    pub fn parse_warning() -> Option<String> {
+        let path = config_path()?;
+        let text = fs::read_to_string(&path).ok()?;
+        let error = toml::from_str::<Config>(&text).err()?;
+        Some(format!(
+            "{}: {}; using the defaults, and the file isn't saved to until it's fixed and reloaded",
+            path.display(), error
+        ))
+    }
+
+    /// Reads the config file again for a live reload. Unlike [`load`](Self::load)
+    /// a missing or broken file is an error, so the settings in use can be kept.
+    pub fn reload() -> Result<Config, String> {
+        let path = config_path().ok_or("no executable directory")?;
+        let text = fs::read_to_string(&path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let config: Config = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.insets.validate()?;
+        for (name, o) in &config.overrides {
+            o.insets.unwrap_or_default().validate().map_err(|e| format!("override for {}: {}", name, e))?;
+        }
+        UNPARSED.store(false, Ordering::SeqCst);
+        Ok(config)
+    }
+
+    /// Writes the config file, unless the one there didn't parse at startup.
+    pub fn save(&self) -> io::Result<()> {
+        let path = config_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no executable directory"))?;
+        if UNPARSED.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not overwriting {}, which didn't parse at startup", path.display()),
+            ));
+        }
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// The saved monitor as a 0-based index, if prompting should be skipped and
+    /// the index is still valid for the current layout.
+    pub fn saved_monitor_index(&self, monitor_count: usize) -> Option<usize> {
+        match self.monitor {
+            Some(n) if self.skip_prompt && n > 0 && n <= monitor_count => Some(n - 1),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Rect and point math on plain `RECT`s and `POINT`s, independent of the
+//! locker's state.
+//!
+//! Rects follow the Win32 convention: `left` and `top` are inside, `right`
+//! and `bottom` are one past the last pixel.
+
+use crate::margin::EdgeMargin;
+use winapi::shared::windef::{POINT, RECT};
+
+/// Whether `pt` is inside `rc`. The right and bottom edges are exclusive,
+/// as everywhere in Win32, so `right` itself is the first column outside.
+pub fn point_in_rect(pt: &POINT, rc: &RECT) -> bool {
+    pt.x >= rc.left && pt.x < rc.right && pt.y >= rc.top && pt.y < rc.bottom
+}
+
+/// The point inside `rc` nearest to `pt`. Like [`point_in_rect`], the right
+/// and bottom edges are exclusive, so the last in-bounds column is `right - 1`.
+pub fn clamp_point_to_rect(pt: &POINT, rc: &RECT) -> POINT {
+    POINT {
+        x: pt.x.clamp(rc.left, (rc.right - 1).max(rc.left)),
+        y: pt.y.clamp(rc.top, (rc.bottom - 1).max(rc.top)),
+    }
+}
+
+/// The middle of `rc`, rounded towards the top-left.
+pub fn rect_center(rc: &RECT) -> POINT {
+    POINT { x: rc.left + (rc.right - rc.left) / 2, y: rc.top + (rc.bottom - rc.top) / 2 }
+}
+
+/// Field-by-field comparison, since `RECT` has no `PartialEq`.
+pub fn rects_equal(a: &RECT, b: &RECT) -> bool {
+    a.left == b.left && a.top == b.top && a.right == b.right && a.bottom == b.bottom
+}
+
+/// Bounding rect of `rects`, plus the rects themselves if their union doesn't
+/// fill it. Monitors don't overlap, so they tile the bounding rect exactly when
+/// their areas add up to its area. Mirrored monitors do overlap; their areas
+/// overshoot and they're kept as a region, which is still correct, just
+/// enforced by warping.
+///
+/// `rects` must not be empty.
+pub fn group_shape(rects: &[RECT]) -> (RECT, Vec<RECT>) {
+    let mut bounds = rects[0];
+    for rc in &rects[1..] {
+        bounds.left = bounds.left.min(rc.left);
+        bounds.top = bounds.top.min(rc.top);
+        bounds.right = bounds.right.max(rc.right);
+        bounds.bottom = bounds.bottom.max(rc.bottom);
+    }
+    let area = |rc: &RECT| (rc.right - rc.left) as i64 * (rc.bottom - rc.top) as i64;
+    let total: i64 = rects.iter().map(area).sum();
+    if total == area(&bounds) {
+        (bounds, Vec::new())
+    } else {
+        (bounds, rects.to_vec())
+    }
+}
+
+/// `pt` if it's inside one of `region`'s rects, otherwise the closest point
+/// that is.
+pub fn clamp_point_to_region(pt: &POINT, region: &[RECT]) -> POINT {
+    if region.iter().any(|rc| point_in_rect(pt, rc)) {
+        return *pt;
+    }
+    let distance = |p: &POINT| {
+        let (dx, dy) = ((p.x - pt.x) as i64, (p.y - pt.y) as i64);
+        dx * dx + dy * dy
+    };
+    region.iter()
+        .map(|rc| clamp_point_to_rect(pt, rc))
+        .min_by_key(distance)
+        .unwrap_or(*pt)
+}
+
+/// Whether `pt` is within `margin` of the outermost row or column of `rc`.
+/// The right and bottom edges are exclusive as in [`point_in_rect`], so the
+/// outermost column on the right is `right - 1` and a zero margin still
+/// catches a cursor clipped against it.
+pub fn at_rect_edge(pt: &POINT, rc: &RECT, margin: EdgeMargin) -> bool {
+    let (mx, my) = margin.to_pixels(rc.right - rc.left, rc.bottom - rc.top);
+    pt.x <= rc.left + mx
+        || pt.x >= rc.right - 1 - mx
+        || pt.y <= rc.top + my
+        || pt.y >= rc.bottom - 1 - my
+}
+
+/// A side of a rect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Top,
+    Right,
+    Bottom,
+}
+
+/// Which edge of `rc` `pt` is within `margin` of, by the same measure as
+/// [`at_rect_edge`]. In a corner the left or right edge wins.
+pub fn rect_edge_at(pt: &POINT, rc: &RECT, margin: EdgeMargin) -> Option<Edge> {
+    let (mx, my) = margin.to_pixels(rc.right - rc.left, rc.bottom - rc.top);
+    if pt.x <= rc.left + mx {
+        Some(Edge::Left)
+    } else if pt.x >= rc.right - 1 - mx {
+        Some(Edge::Right)
+    } else if pt.y <= rc.top + my {
+        Some(Edge::Top)
+    } else if pt.y >= rc.bottom - 1 - my {
+        Some(Edge::Bottom)
+    } else {
+        None
+    }
+}
+
+/// `rc` with its `edge` side moved out to the same side of `bounds`. An edge
+/// already past `bounds` stays where it is.
+pub fn extend_edge(rc: &RECT, edge: Edge, bounds: &RECT) -> RECT {
+    let mut out = *rc;
+    match edge {
+        Edge::Left => out.left = out.left.min(bounds.left),
+        Edge::Top => out.top = out.top.min(bounds.top),
+        Edge::Right => out.right = out.right.max(bounds.right),
+        Edge::Bottom => out.bottom = out.bottom.max(bounds.bottom),
+    }
+    out
+}
+
+/// The nearest point to `pt` inside the ellipse inscribed in `rc`, or `pt`
+/// itself if it's inside already.
+///
+/// With the center at the origin and semi-axes `a` and `b`, the nearest
+/// point on the ellipse is where the normal passes through `pt`. There's no
+/// closed form for that, so it's found iteratively: each step approximates
+/// the ellipse near the current guess by its circle of curvature, whose
+/// center ("evolute" point) is `((a² - b²) cos³t / a, (b² - a²) sin³t / b)`,
+/// and moves the guess to where the line from that center towards `pt`
+/// crosses the circle. Three steps are well within a pixel. The work is done
+/// in the first quadrant and mirrored back, since the ellipse is symmetric.
+pub fn clamp_point_to_ellipse(pt: &POINT, rc: &RECT) -> POINT {
+    // Pixel centers run from left to right - 1, so the axes span that range
+    let a = ((rc.right - rc.left - 1) as f64 / 2.0).max(0.0);
+    let b = ((rc.bottom - rc.top - 1) as f64 / 2.0).max(0.0);
+    let (cx, cy) = (rc.left as f64 + a, rc.top as f64 + b);
+    let (dx, dy) = (pt.x as f64 - cx, pt.y as f64 - cy);
+    if a == 0.0 || b == 0.0 || (dx / a).powi(2) + (dy / b).powi(2) <= 1.0 {
+        return *pt;
+    }
+    let (px, py) = (dx.abs(), dy.abs());
+    let (mut tx, mut ty) = (std::f64::consts::FRAC_1_SQRT_2, std::f64::consts::FRAC_1_SQRT_2);
+    for _ in 0..3 {
+        let (x, y) = (a * tx, b * ty);
+        let ex = (a * a - b * b) * tx.powi(3) / a;
+        let ey = (b * b - a * a) * ty.powi(3) / b;
+        let r = (x - ex).hypot(y - ey);
+        let q = (px - ex).hypot(py - ey);
+        tx = ((px - ex) * r / q + ex) / a;
+        ty = ((py - ey) * r / q + ey) / b;
+        tx = tx.clamp(0.0, 1.0);
+        ty = ty.clamp(0.0, 1.0);
+        let t = tx.hypot(ty);
+        tx /= t;
+        ty /= t;
+    }
+    // Round towards the center so the result is inside, not on the far side
+    // of the boundary
+    let toward_center = |offset: f64, center: f64, d: f64| {
+        let v = center + offset.copysign(d);
+        if d < 0.0 { v.ceil() } else { v.floor() }
+    };
+    POINT {
+        x: toward_center(a * tx, cx, dx) as i32,
+        y: toward_center(b * ty, cy, dy) as i32,
+    }
+}
+
+/// The largest square centered in `rc`, for [`LockShape::Circle`](crate::LockShape::Circle).
+pub fn centered_square(rc: &RECT) -> RECT {
+    let side = (rc.right - rc.left).min(rc.bottom - rc.top);
+    let left = rc.left + (rc.right - rc.left - side) / 2;
+    let top = rc.top + (rc.bottom - rc.top - side) / 2;
+    RECT { left, top, right: left + side, bottom: top + side }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT { left, top, right, bottom }
+    }
+
+    fn pt(x: i32, y: i32) -> POINT {
+        POINT { x, y }
+    }
+
+    /// `POINT` has no `PartialEq` or `Debug`, so compare it as a tuple.
+    fn xy(p: POINT) -> (i32, i32) {
+        (p.x, p.y)
+    }
+
+    #[test]
+    fn point_in_rect_is_half_open() {
+        let rc = rect(0, 0, 1920, 1080);
+        assert!(point_in_rect(&pt(960, 540), &rc));
+        assert!(point_in_rect(&pt(0, 0), &rc));
+        assert!(point_in_rect(&pt(1919, 1079), &rc));
+        assert!(!point_in_rect(&pt(1920, 540), &rc));
+        assert!(!point_in_rect(&pt(960, 1080), &rc));
+        assert!(!point_in_rect(&pt(-1, 540), &rc));
+        assert!(!point_in_rect(&pt(960, -1), &rc));
+    }
+
+    #[test]
+    fn point_in_rect_with_negative_coordinates() {
+        // A monitor left of and above the primary one
+        let rc = rect(-2560, -1440, 0, 0);
+        assert!(point_in_rect(&pt(-2560, -1440), &rc));
+        assert!(point_in_rect(&pt(-1, -1), &rc));
+        assert!(!point_in_rect(&pt(0, -1), &rc));
+        assert!(!point_in_rect(&pt(-1, 0), &rc));
+        assert!(!point_in_rect(&pt(-2561, -720), &rc));
+    }
+
+    #[test]
+    fn point_in_empty_rect() {
+        assert!(!point_in_rect(&pt(0, 0), &rect(0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn at_rect_edge_zero_margin() {
+        let rc = rect(0, 0, 1920, 1080);
+        let margin = EdgeMargin::Pixels(0);
+        assert!(at_rect_edge(&pt(0, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(1919, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(960, 0), &rc, margin));
+        assert!(at_rect_edge(&pt(960, 1079), &rc, margin));
+        assert!(!at_rect_edge(&pt(1, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(1918, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(960, 1), &rc, margin));
+        assert!(!at_rect_edge(&pt(960, 1078), &rc, margin));
+    }
+
+    #[test]
+    fn at_rect_edge_one_pixel_margin() {
+        let rc = rect(0, 0, 1920, 1080);
+        let margin = EdgeMargin::Pixels(1);
+        assert!(at_rect_edge(&pt(1, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(1918, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(960, 1), &rc, margin));
+        assert!(at_rect_edge(&pt(960, 1078), &rc, margin));
+        assert!(!at_rect_edge(&pt(2, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(1917, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(960, 540), &rc, margin));
+    }
+
+    #[test]
+    fn at_rect_edge_outside_counts_as_edge() {
+        let rc = rect(0, 0, 1920, 1080);
+        assert!(at_rect_edge(&pt(-5, 540), &rc, EdgeMargin::Pixels(0)));
+        assert!(at_rect_edge(&pt(1920, 540), &rc, EdgeMargin::Pixels(0)));
+    }
+
+    #[test]
+    fn at_rect_edge_with_negative_coordinates() {
+        let rc = rect(-1920, 0, 0, 1080);
+        let margin = EdgeMargin::Pixels(1);
+        assert!(at_rect_edge(&pt(-1920, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(-1919, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(-1, 540), &rc, margin));
+        assert!(at_rect_edge(&pt(-2, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(-3, 540), &rc, margin));
+        assert!(!at_rect_edge(&pt(-960, 540), &rc, margin));
+    }
+
+    #[test]
+    fn at_rect_edge_percent_margin_scales_per_axis() {
+        let rc = rect(0, 0, 2000, 1000);
+        let margin = EdgeMargin::Percent(1.0);
+        // 20px horizontally, 10px vertically
+        assert!(at_rect_edge(&pt(20, 500), &rc, margin));
+        assert!(!at_rect_edge(&pt(21, 500), &rc, margin));
+        assert!(at_rect_edge(&pt(1000, 10), &rc, margin));
+        assert!(!at_rect_edge(&pt(1000, 11), &rc, margin));
+    }
+
+    #[test]
+    fn clamp_point_to_rect_stops_at_last_pixel() {
+        let rc = rect(0, 0, 1920, 1080);
+        assert_eq!(xy(clamp_point_to_rect(&pt(2500, -40), &rc)), (1919, 0));
+        assert_eq!(xy(clamp_point_to_rect(&pt(-1, 1080), &rc)), (0, 1079));
+        assert_eq!(xy(clamp_point_to_rect(&pt(960, 540), &rc)), (960, 540));
+    }
+
+    #[test]
+    fn clamp_point_to_region_picks_nearest_rect() {
+        // A 1080p monitor with a shorter one to its right, leaving a gap below it
+        let region = [rect(0, 0, 1920, 1080), rect(1920, 0, 3200, 720)];
+        assert_eq!(xy(clamp_point_to_region(&pt(2500, 900), &region)), (2500, 719));
+        assert_eq!(xy(clamp_point_to_region(&pt(1925, 1000), &region)), (1919, 1000));
+        assert_eq!(xy(clamp_point_to_region(&pt(2500, 300), &region)), (2500, 300));
+    }
+
+    #[test]
+    fn group_shape_of_tiling_rects_has_no_region() {
+        let (bounds, region) = group_shape(&[rect(0, 0, 1920, 1080), rect(1920, 0, 3840, 1080)]);
+        assert!(rects_equal(&bounds, &rect(0, 0, 3840, 1080)));
+        assert!(region.is_empty());
+    }
+
+    #[test]
+    fn group_shape_of_mismatched_rects_keeps_them() {
+        let rects = [rect(0, 0, 1920, 1080), rect(1920, 0, 3200, 720)];
+        let (bounds, region) = group_shape(&rects);
+        assert!(rects_equal(&bounds, &rect(0, 0, 3200, 1080)));
+        assert_eq!(region.len(), 2);
+    }
+
+    #[test]
+    fn rect_edge_at_names_the_side() {
+        let rc = rect(0, 0, 1920, 1080);
+        let margin = EdgeMargin::default();
+        assert_eq!(rect_edge_at(&pt(0, 500), &rc, margin), Some(Edge::Left));
+        assert_eq!(rect_edge_at(&pt(1919, 500), &rc, margin), Some(Edge::Right));
+        assert_eq!(rect_edge_at(&pt(960, 0), &rc, margin), Some(Edge::Top));
+        assert_eq!(rect_edge_at(&pt(960, 1079), &rc, margin), Some(Edge::Bottom));
+        assert_eq!(rect_edge_at(&pt(1919, 1079), &rc, margin), Some(Edge::Right));
+        assert_eq!(rect_edge_at(&pt(960, 540), &rc, margin), None);
+    }
+
+    #[test]
+    fn extend_edge_moves_one_side() {
+        let rc = rect(0, 0, 1920, 1080);
+        let bounds = rect(-1920, -200, 3840, 1080);
+        assert!(rects_equal(&extend_edge(&rc, Edge::Right, &bounds), &rect(0, 0, 3840, 1080)));
+        assert!(rects_equal(&extend_edge(&rc, Edge::Left, &bounds), &rect(-1920, 0, 1920, 1080)));
+        assert!(rects_equal(&extend_edge(&rc, Edge::Top, &bounds), &rect(0, -200, 1920, 1080)));
+        // Nothing below the monitor to extend into
+        assert!(rects_equal(&extend_edge(&rc, Edge::Bottom, &bounds), &rc));
+    }
+
+    #[test]
+    fn clamp_point_to_ellipse_lands_inside() {
+        let rc = rect(0, 0, 1920, 1080);
+        let (a, b, cx, cy) = (959.5, 539.5, 959.5, 539.5);
+        for p in [pt(0, 0), pt(1919, 1079), pt(1919, 0), pt(-500, 540), pt(960, 5000)] {
+            let c = clamp_point_to_ellipse(&p, &rc);
+            let (dx, dy) = ((c.x as f64 - cx) / a, (c.y as f64 - cy) / b);
+            assert!(dx * dx + dy * dy <= 1.0, "{:?} clamped to {:?}", (p.x, p.y), (c.x, c.y));
+        }
+        assert_eq!(xy(clamp_point_to_ellipse(&pt(960, 540), &rc)), (960, 540));
+    }
+
+    #[test]
+    fn centered_square_is_centered() {
+        assert!(rects_equal(&centered_square(&rect(0, 0, 1920, 1080)), &rect(420, 0, 1500, 1080)));
+        assert!(rects_equal(&centered_square(&rect(0, 0, 1080, 1920)), &rect(0, 420, 1080, 1500)));
+    }
+}
@@ -0,0 +1,372 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use winapi::um::winuser::{
+    GetAsyncKeyState, MOD_ALT, MOD_CONTROL, MOD_SHIFT, MOD_WIN, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_DELETE,
+    VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_HOME, VK_INSERT, VK_LBUTTON, VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT,
+    VK_LWIN, VK_MBUTTON, VK_MENU, VK_NEXT, VK_NUMPAD0, VK_PAUSE, VK_PRIOR, VK_RBUTTON, VK_RCONTROL, VK_RETURN,
+    VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SCROLL, VK_SHIFT, VK_SNAPSHOT, VK_SPACE, VK_TAB, VK_UP, VK_XBUTTON1,
+    VK_XBUTTON2,
+};
+
+/// A `RegisterHotKey` combination, written like `Ctrl+Alt+L`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hotkey {
+    /// `MOD_*` flags.
+    pub modifiers: u32,
+    /// Virtual-key code of the non-modifier key.
+    pub vk: u32,
+}
+
+impl Hotkey {
+    pub const fn new(modifiers: isize, vk: i32) -> Hotkey {
+        Hotkey { modifiers: modifiers as u32, vk: vk as u32 }
+    }
+
+    /// Whether the key is held right now with exactly its modifiers, the way
+    /// `RegisterHotKey` matches. Used to poll hotkeys that `RegisterHotKey`
+    /// couldn't claim; F11 then doesn't also fire on Ctrl+F11.
+    pub fn is_down(&self) -> bool {
+        modifiers_held(key_down) == self.modifiers && key_down(self.vk as i32)
+    }
+
+    /// Whether the key is a mouse button. `RegisterHotKey` never fires for
+    /// those, so they're always polled.
+    pub fn is_mouse_button(&self) -> bool {
+        MOUSE_BUTTONS.contains(&(self.vk as i32))
+    }
+}
+
+/// The mouse buttons' virtual-key codes, which `GetAsyncKeyState` reports
+/// like keys.
+pub const MOUSE_BUTTONS: [i32; 5] = [VK_LBUTTON, VK_RBUTTON, VK_MBUTTON, VK_XBUTTON1, VK_XBUTTON2];
+
+/// The `MOD_*` flags of the modifier keys `down` reports as held.
+pub fn modifiers_held(down: impl Fn(i32) -> bool) -> u32 {
+    let held = [
+        (MOD_CONTROL, down(VK_CONTROL)),
+        (MOD_ALT, down(VK_MENU)),
+        (MOD_SHIFT, down(VK_SHIFT)),
+        (MOD_WIN, down(VK_LWIN) || down(VK_RWIN)),
+    ];
+    held.iter().filter(|(_, down)| *down).fold(0, |modifiers, (flag, _)| modifiers | *flag as u32)
+}
+
+pub fn key_down(vk: i32) -> bool {
+    unsafe { (GetAsyncKeyState(vk) as i16) < 0 }
+}
+
+/// Turns a polled "is the key down" state into single presses, so holding a
+/// key fires once instead of on every poll.
+#[derive(Default)]
+pub struct KeyEdge {
+    was_down: bool,
+}
+
+impl KeyEdge {
+    /// Feeds the current state; true only on the poll where the key went down.
+    pub fn pressed(&mut self, down: bool) -> bool {
+        let pressed = down && !self.was_down;
+        self.was_down = down;
+        pressed
+    }
+}
+
+const MODIFIER_NAMES: [(&str, isize); 4] = [
+    ("Ctrl", MOD_CONTROL),
+    ("Alt", MOD_ALT),
+    ("Shift", MOD_SHIFT),
+    ("Win", MOD_WIN),
+];
+
+/// Keys known by name, besides letters, digits, `F1`-`F24` and
+/// `Numpad0`-`Numpad9`.
+const NAMED_KEYS: [(&str, i32); 32] = [
+    ("Left", VK_LEFT),
+    ("Right", VK_RIGHT),
+    ("Up", VK_UP),
+    ("Down", VK_DOWN),
+    ("LCtrl", VK_LCONTROL),
+    ("RCtrl", VK_RCONTROL),
+    ("LAlt", VK_LMENU),
+    ("RAlt", VK_RMENU),
+    ("LShift", VK_LSHIFT),
+    ("RShift", VK_RSHIFT),
+    ("LWin", VK_LWIN),
+    ("RWin", VK_RWIN),
+    ("Space", VK_SPACE),
+    ("Tab", VK_TAB),
+    ("Enter", VK_RETURN),
+    ("Esc", VK_ESCAPE),
+    ("Backspace", VK_BACK),
+    ("Insert", VK_INSERT),
+    ("Delete", VK_DELETE),
+    ("Home", VK_HOME),
+    ("End", VK_END),
+    ("PageUp", VK_PRIOR),
+    ("PageDown", VK_NEXT),
+    ("CapsLock", VK_CAPITAL),
+    ("ScrollLock", VK_SCROLL),
+    ("Pause", VK_PAUSE),
+    ("PrintScreen", VK_SNAPSHOT),
+    ("LButton", VK_LBUTTON),
+    ("RButton", VK_RBUTTON),
+    ("MButton", VK_MBUTTON),
+    ("XButton1", VK_XBUTTON1),
+    ("XButton2", VK_XBUTTON2),
+];
+
+/// Either side of a modifier key, for a [`Key`]. In a [`Hotkey`] the same
+/// names are the modifiers, so they're kept out of [`NAMED_KEYS`].
+const EITHER_SIDE_KEYS: [(&str, i32); 3] = [("Ctrl", VK_CONTROL), ("Alt", VK_MENU), ("Shift", VK_SHIFT)];
+
+/// Keys that only work as a [`Key`], not as the key of a [`Hotkey`]: holding
+/// one counts as its modifier, so [`Hotkey::is_down`] would never see it
+/// pressed with no modifiers.
+const MODIFIER_KEYS: [i32; 11] = [
+    VK_CONTROL, VK_LCONTROL, VK_RCONTROL, VK_MENU, VK_LMENU, VK_RMENU, VK_SHIFT, VK_LSHIFT, VK_RSHIFT, VK_LWIN, VK_RWIN,
+];
+
+/// The names [`key_from_name`] takes, for the error on one it doesn't.
+fn supported_key_names() -> String {
+    let named: Vec<&str> = NAMED_KEYS.iter().map(|(name, _)| *name).collect();
+    format!("A-Z, 0-9, F1-F24, Numpad0-Numpad9, {} or a code like 0xA3", named.join(", "))
+}
+
+fn key_from_name(name: &str) -> Option<u32> {
+    if let Some((_, vk)) = NAMED_KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Some(*vk as u32);
+    }
+    if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok().filter(|vk| (1..=0xFE).contains(vk));
+    }
+    let upper = name.to_ascii_uppercase();
+    let bytes = upper.as_bytes();
+    // Letters and digits use their ASCII code as the virtual-key code
+    if bytes.len() == 1 && bytes[0].is_ascii_alphanumeric() {
+        return Some(bytes[0] as u32);
+    }
+    if let Some(digit) = upper.strip_prefix("NUMPAD") {
+        let n: u32 = digit.parse().ok()?;
+        return (n <= 9).then(|| VK_NUMPAD0 as u32 + n);
+    }
+    let n: u32 = upper.strip_prefix('F')?.parse().ok()?;
+    (1..=24).contains(&n).then(|| VK_F1 as u32 + n - 1)
+}
+
+fn key_name(vk: u32) -> String {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, k)| *k as u32 == vk) {
+        return name.to_string();
+    }
+    let (f1, numpad0) = (VK_F1 as u32, VK_NUMPAD0 as u32);
+    if (f1..f1 + 24).contains(&vk) {
+        format!("F{}", vk - f1 + 1)
+    } else if (numpad0..numpad0 + 10).contains(&vk) {
+        format!("Numpad{}", vk - numpad0)
+    } else if let Some(c) = char::from_u32(vk).filter(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        c.to_string()
+    } else {
+        format!("0x{:02X}", vk)
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = 0;
+        let mut vk = None;
+        for part in s.split('+').map(str::trim) {
+            if let Some((_, flag)) = MODIFIER_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(part)) {
+                modifiers |= *flag as u32;
+            } else if vk.is_none() {
+                vk = Some(key_from_name(part).ok_or_else(|| {
+                    format!("Unknown key \"{}\" in hotkey \"{}\" (try {})", part, s, supported_key_names())
+                })?);
+            } else {
+                return Err(format!("Hotkey \"{}\" has more than one non-modifier key", s));
+            }
+        }
+        let vk = vk.ok_or_else(|| format!("Hotkey \"{}\" needs a key besides the modifiers", s))?;
+        if MODIFIER_KEYS.contains(&(vk as i32)) {
+            return Err(format!(
+                "Hotkey \"{}\" is on a modifier key, which only works as a release key; add a key like F11", s
+            ));
+        }
+        Ok(Hotkey { modifiers, vk })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, flag) in MODIFIER_NAMES {
+            if self.modifiers & flag as u32 != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", key_name(self.vk))
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Hotkey> for String {
+    fn from(hotkey: Hotkey) -> Self {
+        hotkey.to_string()
+    }
+}
+
+/// A single key on its own, like a release key, written by name (`"RCtrl"`)
+/// or given as its virtual-key code (`0xA3`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "KeyCode", into = "String")]
+pub struct Key(pub i32);
+
+/// What a [`Key`] can be written as in the config.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyCode {
+    Code(i32),
+    Name(String),
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        if let Some((_, vk)) = EITHER_SIDE_KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            return Ok(Key(*vk));
+        }
+        key_from_name(name)
+            .map(|vk| Key(vk as i32))
+            .ok_or_else(|| format!("Unknown key \"{}\" (try Ctrl, Alt, Shift, {})", s, supported_key_names()))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match EITHER_SIDE_KEYS.iter().find(|(_, vk)| *vk == self.0) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "{}", key_name(self.0 as u32)),
+        }
+    }
+}
+
+impl TryFrom<KeyCode> for Key {
+    type Error = String;
+
+    fn try_from(code: KeyCode) -> Result<Self, Self::Error> {
+        match code {
+            KeyCode::Code(vk) if (1..=0xFE).contains(&vk) => Ok(Key(vk)),
+            KeyCode::Code(vk) => Err(format!("{} is not a virtual-key code (1-254)", vk)),
+            KeyCode::Name(name) => name.parse(),
+        }
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+/// Just the modifier part of a hotkey, written like `Ctrl+Alt`, for hotkeys
+/// that come as a set with different keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Modifiers(pub u32);
+
+impl FromStr for Modifiers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = 0;
+        for part in s.split('+').map(str::trim).filter(|part| !part.is_empty()) {
+            let (_, flag) = MODIFIER_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("\"{}\" in \"{}\" is not a modifier (Ctrl, Alt, Shift or Win)", part, s))?;
+            modifiers |= *flag as u32;
+        }
+        Ok(Modifiers(modifiers))
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = MODIFIER_NAMES.iter()
+            .filter(|(_, flag)| self.0 & *flag as u32 != 0)
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+impl TryFrom<String> for Modifiers {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Modifiers> for String {
+    fn from(modifiers: Modifiers) -> Self {
+        modifiers.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Keys {
+        keys: Vec<Key>,
+    }
+
+    fn keys(toml: &str) -> Result<Vec<i32>, String> {
+        let keys: Keys = toml::from_str(toml).map_err(|e| e.to_string())?;
+        Ok(keys.keys.iter().map(|key| key.0).collect())
+    }
+
+    #[test]
+    fn keys_take_names_and_codes() {
+        assert_eq!(keys(r#"keys = ["RCtrl", "capslock", 0x05, "0xA4", "ctrl"]"#), Ok(vec![0xA3, 0x14, 0x05, 0xA4, 0x11]));
+        let error = keys(r#"keys = ["RightCtrl"]"#).unwrap_err();
+        assert!(error.contains("Unknown key \"RightCtrl\"") && error.contains("RCtrl"), "{}", error);
+        assert!(keys("keys = [0]").is_err());
+        assert!(keys("keys = [0x100]").is_err());
+    }
+
+    #[test]
+    fn key_display_round_trips() {
+        for vk in 1..=0xFE {
+            let key = Key(vk);
+            assert_eq!(key.to_string().parse(), Ok(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn hotkey_display_round_trips() {
+        for vk in (1..=0xFE).filter(|vk| !MODIFIER_KEYS.contains(vk)) {
+            let hotkey = Hotkey::new(MOD_CONTROL | MOD_SHIFT, vk);
+            assert_eq!(hotkey.to_string().parse(), Ok(hotkey), "{}", hotkey);
+        }
+    }
+
+    #[test]
+    fn hotkey_refuses_modifier_keys() {
+        for value in ["RCtrl", "LAlt", "Shift+RShift", "LWin", "Alt+0x11"] {
+            let error = value.parse::<Hotkey>().unwrap_err();
+            assert!(error.contains("modifier key"), "{}: {}", value, error);
+        }
+        // Still fine as release keys
+        assert_eq!("RCtrl".parse(), Ok(Key(VK_RCONTROL)));
+    }
+}
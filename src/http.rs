@@ -0,0 +1,213 @@
+//! `--http`: a minimal HTTP endpoint for switching from another device.
+//!
+//! `GET /status`, `POST /lock/<n>`, `POST /release` and `POST /toggle`, each
+//! answered with a JSON object. Commands go through the same queue as the
+//! control pipe, so the main loop runs them exactly like pipe commands and
+//! its reply line is turned into JSON here.
+//!
+//! Browsers are kept out: a request with an `Origin` header, which any web
+//! page's `fetch` sends, or with a `Host` other than the bound address, as
+//! after DNS rebinding, gets a 403 before it's routed.
+
+use crate::pipe::{Command, Request};
+use serde_json::{Map, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long a client gets to send its request line and headers.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds `addr` and starts serving on a new thread. `notify` is called after
+/// each request is queued, as for the pipe.
+pub fn spawn(addr: SocketAddr, requests: Sender<Request>, notify: impl Fn() + Send + 'static) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let bound = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // One client at a time is plenty for a remote control
+            if !handle(stream, bound, &requests, &notify) {
+                // The main loop is gone
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Serves one request; false once the main loop has stopped taking them.
+fn handle(stream: TcpStream, bound: SocketAddr, requests: &Sender<Request>, notify: &impl Fn()) -> bool {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return true;
+    }
+    // Only Origin and Host matter, but all of them have to be read so the
+    // client doesn't see the connection reset
+    let (mut origin, mut host) = (false, None);
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|n| n > 0) && header.trim() != "" {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("origin") {
+                origin = true;
+            } else if name.trim().eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            }
+        }
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let routed = if origin {
+        Err((403, "requests from web pages are refused".to_string()))
+    } else if !host.as_deref().is_some_and(|host| host_allowed(host, bound)) {
+        Err((403, format!("Host must be the address this listens on, {}", bound)))
+    } else {
+        route(method, path)
+    };
+    let (status, body) = match routed {
+        Ok(command) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            if requests.send(Request { command: Ok(command), reply: reply_tx }).is_err() {
+                return false;
+            }
+            notify();
+            match reply_rx.recv() {
+                Ok(reply) => reply_to_json(&reply),
+                Err(_) => return false,
+            }
+        }
+        Err((status, message)) => (status, error_json(&message)),
+    };
+    respond(&stream, status, &body);
+    true
+}
+
+/// Whether a `Host` header names the address the server is bound to (any IP
+/// address of the machine when bound to all of them), or `localhost` for a
+/// loopback one. A host name other than `localhost` never passes, which is
+/// what stops DNS rebinding.
+fn host_allowed(host: &str, bound: SocketAddr) -> bool {
+    let (name, port) = match host.parse::<SocketAddr>() {
+        Ok(addr) => (addr.ip().to_string(), addr.port()),
+        Err(_) => match host.rsplit_once(':').filter(|(name, _)| !name.ends_with(']') && !name.contains(':')) {
+            Some((name, port)) => match port.parse() {
+                Ok(port) => (name.to_string(), port),
+                Err(_) => return false,
+            },
+            // No port means the default one
+            None => (host.trim_start_matches('[').trim_end_matches(']').to_string(), 80),
+        },
+    };
+    if port != bound.port() {
+        return false;
+    }
+    if name.eq_ignore_ascii_case("localhost") {
+        return bound.ip().is_loopback() || bound.ip().is_unspecified();
+    }
+    match name.parse::<IpAddr>() {
+        Ok(ip) => ip == bound.ip() || bound.ip().is_unspecified(),
+        Err(_) => false,
+    }
+}
+
+/// Maps a request to a command, or to an HTTP status and message.
+fn route(method: &str, path: &str) -> Result<Command, (u16, String)> {
+    let path = path.trim_end_matches('/');
+    let expected = if path == "/status" { "GET" } else { "POST" };
+    let command = match path {
+        "/status" => Command::Status,
+        "/release" => Command::Release,
+        "/toggle" => Command::Toggle,
+        _ => match path.strip_prefix("/lock/") {
+            Some(n) => match n.parse() {
+                Ok(n) if n > 0 => Command::Lock(n),
+                _ => return Err((400, format!("\"{}\" is not a monitor number", n))),
+            },
+            None => return Err((404, format!("no such endpoint {}", path))),
+        },
+    };
+    if method != expected {
+        return Err((405, format!("{} needs {}", path, expected)));
+    }
+    Ok(command)
+}
+
+/// Turns the pipe's `OK key=value ...` / `ERR message` reply into JSON.
+fn reply_to_json(reply: &str) -> (u16, String) {
+    let Some(fields) = reply.strip_prefix("OK ") else {
+        return (409, error_json(reply.strip_prefix("ERR ").unwrap_or(reply)));
+    };
+    let mut object = Map::new();
+    object.insert("ok".to_string(), Value::Bool(true));
+    for (key, value) in fields.split_whitespace().filter_map(|pair| pair.split_once('=')) {
+        let value = match value {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(value.to_string()),
+        };
+        object.insert(key.to_string(), value);
+    }
+    (200, Value::Object(object).to_string())
+}
+
+fn error_json(message: &str) -> String {
+    let mut object = Map::new();
+    object.insert("ok".to_string(), Value::Bool(false));
+    object.insert("error".to_string(), Value::String(message.to_string()));
+    Value::Object(object).to_string()
+}
+
+fn respond(mut stream: &TcpStream, status: u16, body: &str) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Conflict",
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn host_must_be_the_bound_address() {
+        let bound = addr("127.0.0.1:8787");
+        assert!(host_allowed("127.0.0.1:8787", bound));
+        assert!(host_allowed("localhost:8787", bound));
+        assert!(host_allowed("LOCALHOST:8787", bound));
+        assert!(!host_allowed("127.0.0.1:8788", bound));
+        assert!(!host_allowed("127.0.0.1", bound));
+        // A rebound name resolving to 127.0.0.1
+        assert!(!host_allowed("attacker.example:8787", bound));
+        assert!(!host_allowed("192.168.1.5:8787", bound));
+    }
+
+    #[test]
+    fn host_on_all_addresses_takes_any_ip() {
+        let bound = addr("0.0.0.0:80");
+        assert!(host_allowed("192.168.1.5", bound));
+        assert!(host_allowed("[::1]", bound));
+        assert!(host_allowed("localhost", bound));
+        assert!(!host_allowed("pc.local", bound));
+        let bound = addr("[::1]:8787");
+        assert!(host_allowed("[::1]:8787", bound));
+        assert!(!host_allowed("[::2]:8787", bound));
+    }
+}
@@ -0,0 +1,109 @@
+//! `--identify`: big monitor numbers, like the Identify button in Windows'
+//! display settings.
+//!
+//! Each monitor gets a small click-through window in its middle showing its
+//! number from [`get_all_monitors`](lockmousetomonitor::get_all_monitors),
+//! the same one `--list` and `--monitor` use.
+
+use lockmousetomonitor::MonitorInfo;
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::{HWND, RECT};
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::wingdi::{
+    CreateFontW, CreateSolidBrush, DeleteObject, SelectObject, SetBkMode, SetTextColor, ANSI_CHARSET,
+    CLIP_DEFAULT_PRECIS, DEFAULT_PITCH, FW_BOLD, OUT_DEFAULT_PRECIS, RGB, ANTIALIASED_QUALITY, TRANSPARENT,
+};
+use winapi::um::winuser::{
+    BeginPaint, CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, DrawTextW, EndPaint,
+    GetClientRect, GetWindowLongPtrW, PeekMessageW, RegisterClassW, SetLayeredWindowAttributes, SetWindowLongPtrW,
+    ShowWindow, TranslateMessage, DT_CENTER, DT_SINGLELINE, DT_VCENTER, GWLP_USERDATA, LWA_ALPHA, MSG,
+    PAINTSTRUCT, PM_REMOVE, SW_SHOWNOACTIVATE, WM_PAINT, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+};
+
+/// Side of the square each number is drawn in.
+const LABEL_SIZE: i32 = 240;
+
+unsafe extern "system" fn label_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg != WM_PAINT {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    let mut ps: PAINTSTRUCT = std::mem::zeroed();
+    let hdc = BeginPaint(hwnd, &mut ps);
+    let number = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+    let mut text: Vec<u16> = number.to_string().encode_utf16().collect();
+    let font = CreateFontW(
+        LABEL_SIZE * 3 / 4, 0, 0, 0, FW_BOLD, 0, 0, 0, ANSI_CHARSET, OUT_DEFAULT_PRECIS, CLIP_DEFAULT_PRECIS,
+        ANTIALIASED_QUALITY, DEFAULT_PITCH, ptr::null(),
+    );
+    let previous = SelectObject(hdc, font as _);
+    SetBkMode(hdc, TRANSPARENT as i32);
+    SetTextColor(hdc, RGB(255, 255, 255));
+    let mut rc: RECT = std::mem::zeroed();
+    GetClientRect(hwnd, &mut rc);
+    DrawTextW(hdc, text.as_mut_ptr(), text.len() as i32, &mut rc, DT_CENTER | DT_VCENTER | DT_SINGLELINE);
+    SelectObject(hdc, previous);
+    DeleteObject(font as _);
+    EndPaint(hwnd, &ps);
+    0
+}
+
+/// Shows every monitor's number for `duration`, then takes the windows down
+/// again. Returns false if the window class couldn't be registered.
+pub fn show_numbers(monitors: &[MonitorInfo], duration: Duration) -> bool {
+    let class_name: Vec<u16> = "LockMouseToMonitorIdentify\0".encode_utf16().collect();
+    let windows: Vec<HWND> = unsafe {
+        let instance = GetModuleHandleW(ptr::null());
+        let mut wc: WNDCLASSW = std::mem::zeroed();
+        wc.lpfnWndProc = Some(label_proc);
+        wc.hInstance = instance;
+        wc.lpszClassName = class_name.as_ptr();
+        wc.hbrBackground = CreateSolidBrush(RGB(0, 90, 160));
+        if RegisterClassW(&wc) == 0 {
+            return false;
+        }
+        monitors.iter().enumerate().filter_map(|(i, monitor)| {
+            let rc = monitor.rect();
+            let hwnd = CreateWindowExW(
+                WS_EX_LAYERED | WS_EX_TRANSPARENT | WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                WS_POPUP,
+                (rc.left + rc.right - LABEL_SIZE) / 2,
+                (rc.top + rc.bottom - LABEL_SIZE) / 2,
+                LABEL_SIZE,
+                LABEL_SIZE,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                instance,
+                ptr::null_mut(),
+            );
+            if hwnd.is_null() {
+                return None;
+            }
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, (i + 1) as isize);
+            SetLayeredWindowAttributes(hwnd, 0, 220, LWA_ALPHA);
+            ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+            Some(hwnd)
+        }).collect()
+    };
+
+    // The windows only paint while their messages are pumped
+    let until = Instant::now() + duration;
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while Instant::now() < until {
+        while unsafe { PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_REMOVE) } != 0 {
+            unsafe {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        thread::sleep(Duration::from_millis(15));
+    }
+    for hwnd in windows {
+        unsafe { DestroyWindow(hwnd) };
+    }
+    true
+}
@@ -0,0 +1,120 @@
+//! `--input-hook`: a `WH_KEYBOARD_LL` hook as the source of key state.
+//!
+//! Some exclusive-fullscreen games take raw input in a way that makes
+//! `GetAsyncKeyState` miss presses, and `RegisterHotKey` never fires while
+//! they have focus. A low-level hook sees every key before the game does. It
+//! runs on its own thread, which has to pump messages for the hook to be
+//! called, and hands each key event to the main loop over a channel.
+
+use crate::hotkey::{key_down, modifiers_held, Hotkey, MOUSE_BUTTONS};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::{ptr, thread};
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::{
+    CallNextHookEx, GetMessageW, SetWindowsHookExW, UnhookWindowsHookEx, HC_ACTION, KBDLLHOOKSTRUCT,
+    MSG, VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT,
+    VK_SHIFT, WH_KEYBOARD_LL, WM_KEYDOWN, WM_SYSKEYDOWN,
+};
+
+struct KeyEvent {
+    vk: u32,
+    down: bool,
+}
+
+/// Where the hook procedure sends events; it can't capture anything.
+static EVENTS: Mutex<Option<Sender<KeyEvent>>> = Mutex::new(None);
+
+unsafe extern "system" fn hook_proc(code: c_int, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let info = &*(lparam as *const KBDLLHOOKSTRUCT);
+        let down = matches!(wparam as UINT, WM_KEYDOWN | WM_SYSKEYDOWN);
+        if let Some(events) = EVENTS.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            let _ = events.send(KeyEvent { vk: info.vkCode, down });
+        }
+    }
+    // Never swallow the key; the focused program still gets it
+    CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+}
+
+/// Key state as reported by the hook, updated from its events on each poll.
+pub struct KeyHook {
+    events: Receiver<KeyEvent>,
+    held: HashSet<u32>,
+    /// Keys that went down since the previous poll, in order.
+    pressed: Vec<u32>,
+}
+
+impl KeyHook {
+    /// Installs the hook on a new thread; the error is the Win32 error code.
+    pub fn install() -> Result<KeyHook, u32> {
+        let (tx, rx) = mpsc::channel();
+        *EVENTS.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        thread::spawn(move || unsafe {
+            let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), GetModuleHandleW(ptr::null()), 0);
+            if hook.is_null() {
+                let _ = ready_tx.send(Err(GetLastError()));
+                return;
+            }
+            let _ = ready_tx.send(Ok(()));
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {}
+            UnhookWindowsHookEx(hook);
+        });
+        // The thread reports back before it starts pumping
+        ready_rx.recv().unwrap_or(Err(0))?;
+        Ok(KeyHook { events: rx, held: HashSet::new(), pressed: Vec::new() })
+    }
+
+    /// Takes in the events since the last poll.
+    pub fn poll(&mut self) {
+        self.pressed.clear();
+        while let Ok(event) = self.events.try_recv() {
+            self.record(event);
+        }
+        // A keyboard hook doesn't see the mouse buttons, so those are polled
+        for vk in MOUSE_BUTTONS {
+            self.record(KeyEvent { vk: vk as u32, down: key_down(vk) });
+        }
+    }
+
+    fn record(&mut self, event: KeyEvent) {
+        if !event.down {
+            self.held.remove(&event.vk);
+        } else if self.held.insert(event.vk) {
+            // Auto-repeat sends more key-downs while held; only the first counts
+            self.pressed.push(event.vk);
+        }
+    }
+
+    /// Whether `vk` is held. The hook only reports left/right modifier codes,
+    /// so `VK_CONTROL`, `VK_MENU` and `VK_SHIFT` match either side.
+    pub fn is_down(&self, vk: i32) -> bool {
+        sides(vk).iter().any(|vk| self.held.contains(&(*vk as u32)))
+    }
+
+    /// Whether `vk` went down since the previous poll.
+    pub fn was_pressed(&self, vk: i32) -> bool {
+        sides(vk).iter().any(|vk| self.pressed.contains(&(*vk as u32)))
+    }
+
+    /// Whether the hotkey's key went down since the previous poll with
+    /// exactly its modifiers held.
+    pub fn hotkey_pressed(&self, hotkey: &Hotkey) -> bool {
+        self.was_pressed(hotkey.vk as i32) && modifiers_held(|vk| self.is_down(vk)) == hotkey.modifiers
+    }
+}
+
+fn sides(vk: i32) -> Vec<i32> {
+    match vk {
+        VK_CONTROL => vec![VK_LCONTROL, VK_RCONTROL],
+        VK_MENU => vec![VK_LMENU, VK_RMENU],
+        VK_SHIFT => vec![VK_LSHIFT, VK_RSHIFT],
+        _ => vec![vk],
+    }
+}
@@ -0,0 +1,1279 @@
+//! Keep the mouse cursor confined to one monitor on Windows.
+//!
+//! [`MonitorLocker`] enumerates the monitors and manages the lock, either
+//! through `ClipCursor` or by warping the cursor back ("soft lock"). It
+//! doesn't poll anything itself: the caller feeds it cursor positions and
+//! decides when to arm the edge release, which keeps the input handling
+//! (hotkeys, timers, UI) up to the program using it.
+//!
+//! The pieces that don't need a locker are public on their own: [`geometry`]
+//! for the rect and point math, [`monitors`] for enumeration and ordering.
+
+pub mod geometry;
+pub mod margin;
+pub mod monitors;
+
+pub use geometry::{
+    at_rect_edge, centered_square, clamp_point_to_ellipse, clamp_point_to_rect, clamp_point_to_region, extend_edge,
+    group_shape, point_in_rect, rect_center, rect_edge_at, rects_equal, Edge,
+};
+pub use monitors::{
+    enable_dpi_awareness, get_all_monitors, get_all_monitors_by, get_current_monitor_index, get_primary_monitor_index,
+    mirror_of, monitor_index_at, remove_mirrors, sort_monitors, sort_monitors_by, MonitorInfo, MonitorOrder,
+    MonitorSource, SystemMonitors,
+};
+
+use margin::EdgeMargin;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{ptr, thread};
+use winapi::shared::windef::{HMONITOR, POINT, RECT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winuser::{
+    ClipCursor, GetCursorPos, MonitorFromPoint, MONITOR_DEFAULTTONULL, GetClipCursor,
+    GetSystemMetrics, SetCursorPos, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+};
+
+/// Steps and the pause between them for a [smooth](MonitorLocker::set_smooth_warp)
+/// warp: about 6 ms in all, inside even a fast poll interval.
+const SMOOTH_WARP_STEPS: i32 = 4;
+const SMOOTH_WARP_STEP_DELAY: Duration = Duration::from_millis(2);
+
+/// The current clip, unless it's just the whole virtual screen, which is what
+/// `GetClipCursor` reports for a free cursor.
+fn foreign_clip() -> Option<RECT> {
+    let mut current: RECT = unsafe { std::mem::zeroed() };
+    if unsafe { GetClipCursor(&mut current) } == 0 {
+        return None;
+    }
+    let screen = unsafe {
+        let (x, y) = (GetSystemMetrics(SM_XVIRTUALSCREEN), GetSystemMetrics(SM_YVIRTUALSCREEN));
+        RECT { left: x, top: y, right: x + GetSystemMetrics(SM_CXVIRTUALSCREEN), bottom: y + GetSystemMetrics(SM_CYVIRTUALSCREEN) }
+    };
+    (!rects_equal(&current, &screen)).then_some(current)
+}
+
+/// Which directions the lock holds the cursor in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockAxis {
+    #[default]
+    Both,
+    /// Only left/right; the cursor may leave through the top and bottom.
+    X,
+    /// Only up/down; the cursor may leave through the sides.
+    Y,
+}
+
+impl FromStr for LockAxis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "both" => Ok(LockAxis::Both),
+            "x" => Ok(LockAxis::X),
+            "y" => Ok(LockAxis::Y),
+            _ => Err(format!("Invalid lock axis \"{}\": expected x, y or both", s)),
+        }
+    }
+}
+
+/// The area inside the lock rect the cursor is held to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockShape {
+    #[default]
+    Rect,
+    /// The ellipse inscribed in the rect.
+    Ellipse,
+    /// The largest circle centered in the rect.
+    Circle,
+}
+
+impl FromStr for LockShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rect" => Ok(LockShape::Rect),
+            "ellipse" => Ok(LockShape::Ellipse),
+            "circle" => Ok(LockShape::Circle),
+            _ => Err(format!("Invalid shape \"{}\": expected rect, ellipse or circle", s)),
+        }
+    }
+}
+
+/// Pixels to keep clear along each edge of a monitor, e.g. for a docked
+/// sidebar the cursor shouldn't reach.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Insets {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Insets {
+    /// Shrinks `rc` by the insets, always leaving at least one pixel.
+    pub fn apply(&self, rc: &RECT) -> RECT {
+        let left = (rc.left + self.left).min(rc.right - 1);
+        let top = (rc.top + self.top).min(rc.bottom - 1);
+        RECT {
+            left,
+            top,
+            right: (rc.right - self.right).max(left + 1),
+            bottom: (rc.bottom - self.bottom).max(top + 1),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let edges = [("left", self.left), ("top", self.top), ("right", self.right), ("bottom", self.bottom)];
+        match edges.iter().find(|(_, px)| *px < 0) {
+            Some((edge, px)) => Err(format!("Invalid {} inset {}: must not be negative", edge, px)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Settings for one monitor that take the place of the locker-wide ones
+/// while it's locked. Unset fields fall back to those.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorOverride {
+    pub work_area: Option<bool>,
+    pub edge_margin: Option<EdgeMargin>,
+    pub insets: Option<Insets>,
+}
+
+/// What [`MonitorLocker::update`] did with the cursor position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorUpdate {
+    Unchanged,
+    /// The release was armed and the cursor reached the edge; the clip is off.
+    Released,
+    /// The cursor came back onto the locked monitor and the clip is back on.
+    Relocked,
+}
+
+/// Where the lock stands. One value instead of separate clipped, armed and
+/// enabled flags, so there's no combination to end up in that is neither
+/// clipped nor releasable. Every change goes through one transition table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockState {
+    /// The cursor is held to the target.
+    Locked,
+    /// Still held, with the release pending: the next time the cursor touches
+    /// the edge it's let go.
+    ReleasedPending,
+    /// Not held: released, or nothing locked yet. The cursor coming back onto
+    /// the target locks it again.
+    Released,
+    /// Turned off; nothing is clipped or re-applied until it's turned on.
+    Disabled,
+}
+
+/// Something that moves the lock from one [`LockState`] to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transition {
+    /// The clip went on: a lock, a re-lock or the cursor coming back.
+    Engage,
+    /// The release key was pressed.
+    Arm,
+    /// The clip came off.
+    Release,
+    Disable,
+    /// Turned back on; the clip only goes on again with an `Engage`.
+    Enable,
+}
+
+impl LockState {
+    fn after(self, transition: Transition) -> LockState {
+        use LockState::*;
+        match (self, transition) {
+            (Disabled, Transition::Enable) => Released,
+            (Disabled, _) | (_, Transition::Disable) => Disabled,
+            (_, Transition::Engage) => Locked,
+            (Locked | ReleasedPending, Transition::Arm) => ReleasedPending,
+            // Nothing is held, so there's nothing to release
+            (Released, Transition::Arm) => Released,
+            (_, Transition::Release) => Released,
+            (state, Transition::Enable) => state,
+        }
+    }
+
+    /// Whether the cursor is held, released pending or not.
+    pub fn is_clipped(self) -> bool {
+        matches!(self, LockState::Locked | LockState::ReleasedPending)
+    }
+}
+
+/// A change in the lock, passed to the callbacks registered with
+/// [`MonitorLocker::on_state_change`]. `monitors` are indices into
+/// [`MonitorLocker::monitors`], empty for a custom rect.
+#[derive(Clone)]
+pub enum LockEvent {
+    /// The cursor is held again, on the same target as before.
+    Engaged { monitors: Vec<usize>, rect: RECT },
+    /// The cursor is free. The target is kept for re-locking.
+    Released { monitors: Vec<usize> },
+    /// The lock moved to a different target or rect: a switch, a group, or a
+    /// display change. The cursor is held unless the lock is disabled.
+    Switched { monitors: Vec<usize>, rect: RECT },
+}
+
+type Observer = Box<dyn Fn(&LockEvent)>;
+
+/// What [`MonitorLocker::refresh_monitors`] did with the lock.
+#[derive(Clone, Copy)]
+pub enum Refresh {
+    Unchanged,
+    /// The locked monitor is still there but its rect changed.
+    Moved(RECT),
+    /// The locked monitor went away; the lock moved to the primary monitor.
+    FellBackToPrimary(RECT),
+    /// No monitor was left to lock to, so the clip was released.
+    NoMonitors,
+}
+
+/// Why a lock couldn't be set up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LockError {
+    /// Enumeration found no monitors.
+    NoMonitors,
+    /// The monitors asked for don't exist, or nothing was asked for.
+    InvalidSelection(String),
+    /// `ClipCursor` failed, with the `GetLastError` code.
+    ClipFailed(u32),
+    /// A setting that can't work, like insets that leave nothing to lock to.
+    InvalidSetting(String),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::NoMonitors => write!(f, "No monitors found!"),
+            LockError::InvalidSelection(message) | LockError::InvalidSetting(message) => write!(f, "{}", message),
+            LockError::ClipFailed(code) => write!(f, "Failed to confine the cursor (ClipCursor error {})", code),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+/// Outcome of [`MonitorLocker::reapply`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reapply {
+    /// The clip was still in place, or nothing needed doing.
+    Unchanged,
+    /// Something (another program, alt-tabbing) had changed the clip, and it
+    /// was put back.
+    Restored,
+    /// `ClipCursor` started failing, with the `GetLastError` code. Reported
+    /// once; later attempts keep retrying quietly until one succeeds.
+    Failed(u32),
+    /// The clip stuck again after failing.
+    Recovered,
+}
+
+/// Owns the cursor lock: which monitor it's on and whether it's engaged.
+pub struct MonitorLocker {
+    source: Box<dyn MonitorSource>,
+    monitors: Vec<MonitorInfo>,
+    /// Leave mirror clones out of `monitors`.
+    skip_mirrors: bool,
+    order: MonitorOrder,
+    work_area: bool,
+    insets: Insets,
+    edge_margin: EdgeMargin,
+    /// How far back inside the rect a released cursor has to come before it's
+    /// re-locked; `None` re-locks on the first pixel.
+    relock_inset: Option<EdgeMargin>,
+    /// Per-monitor settings, by device name.
+    overrides: HashMap<String, MonitorOverride>,
+    /// The monitor the lock belongs to; `current_rect` is its (work) rect.
+    /// `None` with a `current_rect` means a custom rect from `lock_to_rect`.
+    locked: Option<HMONITOR>,
+    /// The other monitors of a group lock; empty when locked to one monitor.
+    group: Vec<HMONITOR>,
+    /// The pieces of a group lock whose union isn't a rectangle. The clip is
+    /// then the bounding `current_rect` and each [`reapply`](Self::reapply)
+    /// warps the cursor out of the gaps. Empty whenever the clip alone is exact.
+    region: Vec<RECT>,
+    current_rect: Option<RECT>,
+    state: LockState,
+    soft_lock: bool,
+    axis: LockAxis,
+    shape: LockShape,
+    smooth_warp: bool,
+    /// Clip and warp on every reapply, see [`set_sticky`](Self::set_sticky).
+    sticky: bool,
+    /// The edge a [`widen`](Self::widen) has opened the clip on.
+    widened: Option<Edge>,
+    /// Set while `ClipCursor` keeps failing, e.g. on the secure desktop
+    /// during a UAC prompt or Win+L.
+    clip_failing: bool,
+    /// Another program's clip from before ours, put back on release instead
+    /// of freeing the cursor entirely.
+    saved_clip: Option<RECT>,
+    observers: Vec<Observer>,
+}
+
+impl Default for MonitorLocker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MonitorLocker {
+    /// Enumerates the monitors. Nothing is locked until [`lock_to`](Self::lock_to).
+    pub fn new() -> Self {
+        Self::with_source(SystemMonitors)
+    }
+
+    /// Like [`new`](Self::new), but numbers the monitors in `order` from the
+    /// start, without the second enumeration of
+    /// [`set_monitor_order`](Self::set_monitor_order).
+    pub fn with_order(order: MonitorOrder) -> Self {
+        Self::from_source(SystemMonitors, order)
+    }
+
+    /// A locker that takes its monitors from `source` instead of Windows.
+    /// Locking still goes through `ClipCursor`.
+    pub fn with_source(source: impl MonitorSource + 'static) -> Self {
+        Self::from_source(source, MonitorOrder::Left)
+    }
+
+    fn from_source(source: impl MonitorSource + 'static, order: MonitorOrder) -> Self {
+        let mut monitors = source.monitors();
+        sort_monitors_by(&mut monitors, order);
+        MonitorLocker {
+            monitors,
+            source: Box::new(source),
+            skip_mirrors: false,
+            order,
+            work_area: false,
+            insets: Insets::default(),
+            edge_margin: EdgeMargin::default(),
+            relock_inset: None,
+            overrides: HashMap::new(),
+            locked: None,
+            group: Vec::new(),
+            region: Vec::new(),
+            current_rect: None,
+            state: LockState::Released,
+            soft_lock: false,
+            axis: LockAxis::Both,
+            shape: LockShape::Rect,
+            smooth_warp: false,
+            sticky: false,
+            widened: None,
+            clip_failing: false,
+            saved_clip: foreign_clip(),
+            observers: Vec::new(),
+        }
+    }
+
+    /// The clip that was in place before the locker was created, which
+    /// [`release`](Self::release) and friends restore. `None` if the cursor
+    /// was free.
+    pub fn saved_clip(&self) -> Option<RECT> {
+        self.saved_clip
+    }
+
+    /// Drops our clip, back to the saved one if there was one.
+    fn unclip(&self) {
+        let saved = self.saved_clip.as_ref().map_or(ptr::null(), |rc| rc as *const RECT);
+        unsafe { ClipCursor(saved) };
+    }
+
+    /// Calls `callback` on every [`LockEvent`], so a frontend can follow the
+    /// lock without comparing state after each call. Callbacks run inside
+    /// the locker method that caused the change.
+    pub fn on_state_change(&mut self, callback: impl Fn(&LockEvent) + 'static) {
+        self.observers.push(Box::new(callback));
+    }
+
+    fn notify(&self, event: LockEvent) {
+        for observer in &self.observers {
+            observer(&event);
+        }
+    }
+
+    /// [`LockEvent::Engaged`] for the current target.
+    fn notify_engaged(&self) {
+        if let Some(rect) = self.current_rect {
+            self.notify(LockEvent::Engaged { monitors: self.current_monitors(), rect });
+        }
+    }
+
+    fn notify_released(&self) {
+        self.notify(LockEvent::Released { monitors: self.current_monitors() });
+    }
+
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        &self.monitors
+    }
+
+    /// Numbers [`monitors`](Self::monitors) in `order` from now on (left to
+    /// right by default). Call it before locking anything, as it changes
+    /// which index is which.
+    pub fn set_monitor_order(&mut self, order: MonitorOrder) {
+        self.order = order;
+        self.monitors = self.source.monitors();
+        sort_monitors_by(&mut self.monitors, order);
+        if self.skip_mirrors {
+            remove_mirrors(&mut self.monitors);
+        }
+    }
+
+    /// Leaves monitors that only mirror another (see [`mirror_of`]) out of
+    /// [`monitors`](Self::monitors), now and after every refresh, so they
+    /// don't take up a monitor number. Call it before locking anything.
+    pub fn set_skip_mirrors(&mut self, skip: bool) {
+        self.skip_mirrors = skip;
+        if skip {
+            remove_mirrors(&mut self.monitors);
+        }
+    }
+
+    pub fn work_area(&self) -> bool {
+        self.work_area
+    }
+
+    /// Lock to monitors' work areas rather than their full rects. Takes effect
+    /// on the next [`lock_to`](Self::lock_to).
+    pub fn set_work_area(&mut self, work_area: bool) {
+        self.work_area = work_area;
+    }
+
+    pub fn edge_margin(&self) -> EdgeMargin {
+        self.edge_margin
+    }
+
+    pub fn set_edge_margin(&mut self, margin: EdgeMargin) {
+        self.edge_margin = margin;
+    }
+
+    /// Keeps a released cursor free until it's past `inset` from the edge, so
+    /// one that hovers on the boundary doesn't keep getting caught again.
+    pub fn set_relock_inset(&mut self, inset: Option<EdgeMargin>) {
+        self.relock_inset = inset;
+    }
+
+    pub fn insets(&self) -> Insets {
+        self.insets
+    }
+
+    /// Keeps the cursor off strips along the monitor edges. Like the work-area
+    /// setting it's applied by [`lock_rect`](Self::lock_rect), so it takes
+    /// effect on the next lock and follows display changes.
+    pub fn set_insets(&mut self, insets: Insets) {
+        self.insets = insets;
+    }
+
+    /// Replaces the per-monitor overrides, keyed by device name like
+    /// `\\.\DISPLAY1` (matched ignoring case). Like the settings they
+    /// override, they take effect on the next lock.
+    pub fn set_monitor_overrides(&mut self, overrides: HashMap<String, MonitorOverride>) {
+        self.overrides = overrides;
+    }
+
+    fn override_for(&self, monitor: &MonitorInfo) -> MonitorOverride {
+        self.overrides.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&monitor.device_name))
+            .map(|(_, o)| *o)
+            .unwrap_or_default()
+    }
+
+    /// The rect to clip to for `monitor`, honoring the work-area setting and
+    /// the insets, or the monitor's overrides of them.
+    pub fn lock_rect(&self, monitor: &MonitorInfo) -> RECT {
+        let o = self.override_for(monitor);
+        let rc = if o.work_area.unwrap_or(self.work_area) { monitor.work_rect } else { monitor.rect };
+        o.insets.unwrap_or(self.insets).apply(&rc)
+    }
+
+    /// The edge margin for the locked monitor, after its override.
+    fn current_edge_margin(&self) -> EdgeMargin {
+        let locked = self.locked.and_then(|h| self.monitors.iter().find(|m| m.handle == h));
+        locked.and_then(|m| self.override_for(m).edge_margin).unwrap_or(self.edge_margin)
+    }
+
+    /// Clips the cursor to the monitor at `index` in [`monitors`](Self::monitors).
+    /// Returns false if the index is out of range or `ClipCursor` failed; see
+    /// [`try_lock_to`](Self::try_lock_to) for which.
+    pub fn lock_to(&mut self, index: usize) -> bool {
+        self.try_lock_to(index).is_ok()
+    }
+
+    pub fn try_lock_to(&mut self, index: usize) -> Result<(), LockError> {
+        self.try_lock_to_group(&[index])
+    }
+
+    /// Clips the cursor to an arbitrary rect that isn't tied to a monitor, such
+    /// as a window. Display changes leave it alone.
+    pub fn lock_to_rect(&mut self, rc: RECT) -> bool {
+        self.try_lock_to_rect(rc).is_ok()
+    }
+
+    pub fn try_lock_to_rect(&mut self, rc: RECT) -> Result<(), LockError> {
+        self.lock_to_target(rc, None, Vec::new(), Vec::new())
+    }
+
+    /// Locks to several monitors at once, given as indices into
+    /// [`monitors`](Self::monitors). Returns false if any index is out of
+    /// range or `ClipCursor` failed.
+    ///
+    /// When the monitors tile a rectangle the clip is exactly their union.
+    /// Otherwise (an L shape, or a gap between them) the clip is their bounding
+    /// rect and the cursor is warped back into the nearest monitor whenever it
+    /// strays into a part of that rect no selected monitor covers.
+    pub fn lock_to_group(&mut self, indices: &[usize]) -> bool {
+        self.try_lock_to_group(indices).is_ok()
+    }
+
+    /// [`lock_to_group`](Self::lock_to_group), saying what went wrong.
+    pub fn try_lock_to_group(&mut self, indices: &[usize]) -> Result<(), LockError> {
+        if self.monitors.is_empty() {
+            return Err(LockError::NoMonitors);
+        }
+        if let Some(&i) = indices.iter().find(|&&i| i >= self.monitors.len()) {
+            return Err(LockError::InvalidSelection(format!(
+                "Monitor {} does not exist (found {} monitors)", i + 1, self.monitors.len())));
+        }
+        if indices.is_empty() {
+            return Err(LockError::InvalidSelection("No monitors to lock to".to_string()));
+        }
+        let handles: Vec<HMONITOR> = indices.iter().map(|&i| self.monitors[i].handle).collect();
+        let rects: Vec<RECT> = indices.iter().map(|&i| self.lock_rect(&self.monitors[i])).collect();
+        let (bounds, region) = group_shape(&rects);
+        self.lock_to_target(bounds, Some(handles[0]), handles[1..].to_vec(), region)
+    }
+
+    fn lock_to_target(
+        &mut self,
+        rc: RECT,
+        monitor: Option<HMONITOR>,
+        group: Vec<HMONITOR>,
+        region: Vec<RECT>,
+    ) -> Result<(), LockError> {
+        if self.is_enabled() {
+            self.engage(&rc, &region).map_err(LockError::ClipFailed)?;
+        }
+        let same_target = self.locked == monitor
+            && self.group == group
+            && self.current_rect.is_some_and(|cur| rects_equal(&cur, &rc));
+        self.locked = monitor;
+        self.group = group;
+        self.region = region;
+        self.current_rect = Some(rc);
+        self.widened = None;
+        self.state = self.state.after(Transition::Engage);
+        if !same_target {
+            self.notify(LockEvent::Switched { monitors: self.current_monitors(), rect: rc });
+        } else if self.is_clipped() {
+            self.notify_engaged();
+        }
+        Ok(())
+    }
+
+    /// Drops the lock entirely, unlike [`release`](Self::release) which keeps
+    /// the target around to re-lock on.
+    pub fn unlock(&mut self) {
+        self.release();
+        self.locked = None;
+        self.group.clear();
+        self.region.clear();
+        self.current_rect = None;
+    }
+
+    /// Soft lock confines the cursor by warping it back with `SetCursorPos`
+    /// on every [`reapply`](Self::reapply) instead of using `ClipCursor`,
+    /// for fullscreen games that reset the OS clip every frame.
+    pub fn set_soft_lock(&mut self, soft_lock: bool) {
+        self.soft_lock = soft_lock;
+    }
+
+    /// Sticky mode calls `ClipCursor` on every [`reapply`](Self::reapply),
+    /// even when the clip looks intact, and also warps the cursor back if it
+    /// got out anyway. For games that move the cursor on focus changes.
+    pub fn set_sticky(&mut self, sticky: bool) {
+        self.sticky = sticky;
+    }
+
+    pub fn is_sticky(&self) -> bool {
+        self.sticky
+    }
+
+    /// Glides the cursor back in a few steps when it's warped inside the lock
+    /// (soft lock, an axis or shape lock, or a gap in a group) rather than
+    /// jumping there. Off by default, as the jump is exact.
+    pub fn set_smooth_warp(&mut self, smooth: bool) {
+        self.smooth_warp = smooth;
+    }
+
+    /// Holds the cursor along one axis only. `ClipCursor` can't do that, so
+    /// like soft lock this warps the cursor back on every
+    /// [`reapply`](Self::reapply), correcting just the chosen coordinate.
+    pub fn set_lock_axis(&mut self, axis: LockAxis) {
+        self.axis = axis;
+    }
+
+    pub fn lock_axis(&self) -> LockAxis {
+        self.axis
+    }
+
+    /// Holds the cursor to an ellipse or circle inside the lock rect. The OS
+    /// clip is still the rect; every [`reapply`](Self::reapply) moves the
+    /// cursor back onto the shape when it's strayed into a corner.
+    pub fn set_shape(&mut self, shape: LockShape) {
+        self.shape = shape;
+    }
+
+    pub fn shape(&self) -> LockShape {
+        self.shape
+    }
+
+    pub fn soft_lock(&self) -> bool {
+        self.soft_lock
+    }
+
+    /// Whether the lock is enforced with `ClipCursor`, as opposed to only
+    /// warping the cursor back.
+    fn uses_clip(&self) -> bool {
+        !self.soft_lock && self.axis == LockAxis::Both
+    }
+
+    /// Whether the cursor has to be checked and warped back on every
+    /// [`reapply`](Self::reapply), because the OS clip alone can't hold it.
+    fn needs_warp(&self, region: &[RECT]) -> bool {
+        self.sticky || !self.uses_clip() || !region.is_empty() || self.shape != LockShape::Rect
+    }
+
+    /// Confines the cursor to `rc`, and to `region` when that's non-empty.
+    /// Returns the `GetLastError` code on failure.
+    fn engage(&self, rc: &RECT, region: &[RECT]) -> Result<(), u32> {
+        if self.uses_clip() {
+            if unsafe { ClipCursor(rc) } == 0 {
+                return Err(unsafe { GetLastError() });
+            }
+        } else {
+            // Drop a clip left over from before switching to soft lock
+            self.unclip();
+        }
+        if self.needs_warp(region) {
+            self.warp_into(rc, region)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the OS clip is still exactly `rc`.
+    fn clip_intact(rc: &RECT) -> bool {
+        let mut current: RECT = unsafe { std::mem::zeroed() };
+        let ok = unsafe { GetClipCursor(&mut current) } != 0;
+        ok && rects_equal(&current, rc)
+    }
+
+    /// Moves the cursor back inside `rc` (or `region`) along the locked axes.
+    fn warp_into(&self, rc: &RECT, region: &[RECT]) -> Result<(), u32> {
+        let mut pt: POINT = unsafe { std::mem::zeroed() };
+        if unsafe { GetCursorPos(&mut pt) } == 0 {
+            return Err(unsafe { GetLastError() });
+        }
+        let clamped = self.confine(&pt, rc, region);
+        if clamped.x != pt.x || clamped.y != pt.y {
+            self.move_cursor(&pt, &clamped);
+        }
+        Ok(())
+    }
+
+    /// Where the lock puts a cursor at `pt`: the nearest point of the rect or
+    /// region, then of the shape, on the locked axes only.
+    fn confine(&self, pt: &POINT, rc: &RECT, region: &[RECT]) -> POINT {
+        let clamped = if region.is_empty() {
+            clamp_point_to_rect(pt, rc)
+        } else {
+            clamp_point_to_region(pt, region)
+        };
+        let clamped = match self.shape {
+            LockShape::Rect => clamped,
+            LockShape::Ellipse => clamp_point_to_ellipse(&clamped, rc),
+            LockShape::Circle => clamp_point_to_ellipse(&clamped, &centered_square(rc)),
+        };
+        match self.axis {
+            LockAxis::Both => clamped,
+            LockAxis::X => POINT { x: clamped.x, y: pt.y },
+            LockAxis::Y => POINT { x: pt.x, y: clamped.y },
+        }
+    }
+
+    fn move_cursor(&self, from: &POINT, to: &POINT) {
+        if self.smooth_warp {
+            for step in 1..SMOOTH_WARP_STEPS {
+                let x = from.x + (to.x - from.x) * step / SMOOTH_WARP_STEPS;
+                let y = from.y + (to.y - from.y) * step / SMOOTH_WARP_STEPS;
+                unsafe { SetCursorPos(x, y) };
+                thread::sleep(SMOOTH_WARP_STEP_DELAY);
+            }
+        }
+        unsafe { SetCursorPos(to.x, to.y) };
+    }
+
+    /// Removes the clip. The locked monitor is kept, so [`update`](Self::update)
+    /// re-locks once the cursor is back on it.
+    pub fn release(&mut self) {
+        self.unclip();
+        self.widened = None;
+        let was_clipped = self.is_clipped();
+        self.state = self.state.after(Transition::Release);
+        if was_clipped {
+            self.notify_released();
+        }
+    }
+
+    /// The edge of the lock `pt` is at, judged with the edge margin, if the
+    /// clip is on.
+    pub fn edge_at(&self, pt: &POINT) -> Option<Edge> {
+        let rc = self.current_rect.filter(|_| self.is_clipped())?;
+        rect_edge_at(pt, &rc, self.current_edge_margin())
+    }
+
+    /// Opens the clip on `edge` out to the far side of the desktop, so the
+    /// cursor (and a window being dragged) can cross it, until
+    /// [`end_widen`](Self::end_widen). The other edges hold as before. Only
+    /// works while clipped with `ClipCursor`; returns false otherwise.
+    pub fn widen(&mut self, edge: Edge) -> bool {
+        let Some(rc) = self.current_rect else { return false };
+        if self.state != LockState::Locked || !self.uses_clip() || self.monitors.is_empty() {
+            return false;
+        }
+        self.widened = Some(edge);
+        let clip = self.clip_rect(&rc);
+        self.clip_failing = unsafe { ClipCursor(&clip) } == 0;
+        true
+    }
+
+    pub fn widened(&self) -> Option<Edge> {
+        self.widened
+    }
+
+    /// Closes a [`widen`](Self::widen)ed edge again. `ClipCursor` pulls a
+    /// cursor that's out past it back inside.
+    pub fn end_widen(&mut self) {
+        if self.widened.take().is_none() || self.state != LockState::Locked {
+            return;
+        }
+        if let Some(rc) = self.current_rect {
+            self.clip_failing = self.engage(&rc, &self.region).is_err();
+        }
+    }
+
+    /// What `ClipCursor` is called with for the lock rect `rc`: `rc` itself,
+    /// or with the widened edge pushed out to the bounds of all monitors.
+    fn clip_rect(&self, rc: &RECT) -> RECT {
+        match self.widened {
+            Some(edge) if !self.monitors.is_empty() => {
+                let rects: Vec<RECT> = self.monitors.iter().map(|m| m.rect).collect();
+                extend_edge(rc, edge, &group_shape(&rects).0)
+            }
+            _ => *rc,
+        }
+    }
+
+    /// Re-applies a [`release`](Self::release)d clip straight away, wherever
+    /// the cursor is; `ClipCursor` pulls it back inside. Returns false if
+    /// there's nothing to lock to or the lock is disabled.
+    pub fn relock(&mut self) -> bool {
+        let Some(rc) = self.current_rect.filter(|_| self.is_enabled()) else {
+            return false;
+        };
+        self.clip_failing = self.engage(&rc, &self.region).is_err();
+        self.state = self.state.after(Transition::Engage);
+        self.notify_engaged();
+        true
+    }
+
+    /// Index of the locked monitor in [`monitors`](Self::monitors).
+    pub fn current_monitor(&self) -> Option<usize> {
+        let locked = self.locked?;
+        self.monitors.iter().position(|m| m.handle == locked)
+    }
+
+    /// Indices of every locked monitor, the first being
+    /// [`current_monitor`](Self::current_monitor).
+    pub fn current_monitors(&self) -> Vec<usize> {
+        self.locked.iter().chain(&self.group)
+            .filter_map(|&h| self.monitors.iter().position(|m| m.handle == h))
+            .collect()
+    }
+
+    /// The rect the cursor is locked to. For a group lock this is the union of
+    /// the monitors' rects, or their bounding rect if they don't tile one; see
+    /// [`region`](Self::region).
+    pub fn current_rect(&self) -> Option<RECT> {
+        self.current_rect
+    }
+
+    /// The monitor rects of a group lock that doesn't form a rectangle, which
+    /// the cursor is kept within. Empty when [`current_rect`](Self::current_rect)
+    /// is the whole story.
+    pub fn region(&self) -> &[RECT] {
+        &self.region
+    }
+
+    /// Whether the cursor may be at `x`, `y` under the lock as it stands: inside
+    /// the rect or a group's monitors, and the ellipse or circle with
+    /// `--shape`. With an axis lock only that coordinate is held. Always
+    /// false while the cursor is free.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let Some(rc) = self.current_rect.filter(|_| self.is_clipped()) else {
+            return false;
+        };
+        let pt = POINT { x, y };
+        if self.axis == LockAxis::Both && self.shape == LockShape::Rect {
+            return self.in_target(&pt, &rc);
+        }
+        let held = self.confine(&pt, &rc, &self.region);
+        held.x == x && held.y == y
+    }
+
+    fn in_target(&self, pt: &POINT, rc: &RECT) -> bool {
+        if self.region.is_empty() {
+            point_in_rect(pt, rc)
+        } else {
+            self.region.iter().any(|r| point_in_rect(pt, r))
+        }
+    }
+
+    pub fn state(&self) -> LockState {
+        self.state
+    }
+
+    pub fn is_clipped(&self) -> bool {
+        self.state.is_clipped()
+    }
+
+    /// Arms the edge release: the next time [`update`](Self::update) sees the
+    /// cursor at the monitor edge, the clip is removed. Does nothing unless
+    /// the cursor is held.
+    pub fn arm_release(&mut self) {
+        self.state = self.state.after(Transition::Arm);
+    }
+
+    pub fn is_release_armed(&self) -> bool {
+        self.state == LockState::ReleasedPending
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state != LockState::Disabled
+    }
+
+    /// Turns the whole lock on or off, independent of the edge release.
+    /// Disabling removes the clip; enabling clips to the locked monitor again.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.is_enabled() {
+            return;
+        }
+        if enabled {
+            self.state = self.state.after(Transition::Enable);
+            if let Some(rc) = &self.current_rect {
+                self.clip_failing = self.engage(rc, &self.region).is_err();
+                self.state = self.state.after(Transition::Engage);
+                self.notify_engaged();
+            }
+        } else {
+            self.unclip();
+            let was_clipped = self.is_clipped();
+            self.state = self.state.after(Transition::Disable);
+            if was_clipped {
+                self.notify_released();
+            }
+        }
+    }
+
+    /// Moves the cursor to the middle of the locked rect.
+    pub fn warp_to_center(&self) {
+        if let Some(rc) = &self.current_rect {
+            let center = rect_center(rc);
+            unsafe { SetCursorPos(center.x, center.y) };
+        }
+    }
+
+    /// Index of the monitor with this handle, e.g. from `MonitorFromWindow`.
+    pub fn monitor_index(&self, handle: HMONITOR) -> Option<usize> {
+        self.monitors.iter().position(|m| m.handle == handle)
+    }
+
+    /// Index of the monitor whose rect contains `pt`, going by
+    /// [`monitors`](Self::monitors) alone; `None` in a gap between them.
+    pub fn monitor_at(&self, pt: &POINT) -> Option<usize> {
+        monitor_index_at(&self.monitors, pt)
+    }
+
+    /// Re-applies the clip if the lock should be engaged. Other programs (and
+    /// alt-tabbing) can reset the clip, so call this regularly. `ClipCursor`
+    /// is only called when `GetClipCursor` shows the clip has changed.
+    ///
+    /// A failing `ClipCursor` is retried on every call until it sticks, so the
+    /// lock comes back as soon as the secure desktop is dismissed.
+    pub fn reapply(&mut self) -> Reapply {
+        if self.state != LockState::Locked {
+            return Reapply::Unchanged;
+        }
+        let Some(rc) = self.current_rect else {
+            return Reapply::Unchanged;
+        };
+        let mut result = Ok(false);
+        let clip = self.clip_rect(&rc);
+        let intact = !self.uses_clip() || Self::clip_intact(&clip);
+        if self.uses_clip() && (self.sticky || self.clip_failing || !intact) {
+            // Only a clip that had actually changed counts as restored
+            result = match unsafe { ClipCursor(&clip) } {
+                0 => Err(unsafe { GetLastError() }),
+                _ => Ok(!intact),
+            };
+        }
+        // Warping would pull the cursor back across a widened edge
+        if result.is_ok() && self.widened.is_none() && self.needs_warp(&self.region) {
+            if let Err(code) = self.warp_into(&rc, &self.region) {
+                result = Err(code);
+            }
+        }
+        match result {
+            Ok(_) if self.clip_failing => {
+                self.clip_failing = false;
+                Reapply::Recovered
+            }
+            Ok(true) => Reapply::Restored,
+            Ok(false) => Reapply::Unchanged,
+            Err(_) if self.clip_failing => Reapply::Unchanged,
+            Err(code) => {
+                self.clip_failing = true;
+                Reapply::Failed(code)
+            }
+        }
+    }
+
+    /// Catches a lock rect that outlived its monitor's old resolution before
+    /// `WM_DISPLAYCHANGE` is handled (or when it never arrives): if `pt` is
+    /// inside the lock but on no monitor at all, the monitors are refreshed.
+    /// Returns the refresh outcome when one was needed.
+    pub fn check_stale_rect(&mut self, pt: &POINT) -> Option<Refresh> {
+        let rc = self.current_rect?;
+        if self.locked.is_none() || !self.is_clipped() || !self.in_target(pt, &rc) {
+            return None;
+        }
+        if !unsafe { MonitorFromPoint(*pt, MONITOR_DEFAULTTONULL) }.is_null() {
+            return None;
+        }
+        Some(self.refresh_monitors())
+    }
+
+    /// Whether the last attempt to apply the clip failed.
+    pub fn is_clip_failing(&self) -> bool {
+        self.clip_failing
+    }
+
+    /// Runs the edge release and re-lock logic for the cursor at `pt`.
+    pub fn update(&mut self, pt: &POINT) -> CursorUpdate {
+        let Some(rc) = self.current_rect else {
+            return CursorUpdate::Unchanged;
+        };
+        if self.state == LockState::ReleasedPending && at_rect_edge(pt, &rc, self.current_edge_margin()) {
+            self.unclip();
+            self.state = self.state.after(Transition::Release);
+            self.notify_released();
+            CursorUpdate::Released
+        } else if self.state == LockState::Released
+            && self.in_target(pt, &rc)
+            && !self.relock_inset.is_some_and(|inset| at_rect_edge(pt, &rc, inset))
+        {
+            // Re-lock when returning to monitor; a failure is picked up and
+            // retried by the next reapply
+            self.clip_failing = self.engage(&rc, &self.region).is_err();
+            self.state = self.state.after(Transition::Engage);
+            self.notify_engaged();
+            CursorUpdate::Relocked
+        } else {
+            CursorUpdate::Unchanged
+        }
+    }
+
+    /// The monitor in the fresh enumeration that `old` has become. Handles
+    /// aren't guaranteed to survive a topology change, so this goes by device
+    /// name, then by whichever monitor now holds the old rect's center.
+    fn find_again(&self, old: &MonitorInfo) -> Option<&MonitorInfo> {
+        if let Some(m) = self.monitors.iter().find(|m| m.device_name == old.device_name) {
+            return Some(m);
+        }
+        let hmon = unsafe { MonitorFromPoint(rect_center(&old.rect), MONITOR_DEFAULTTONULL) };
+        self.monitors.iter().find(|m| m.handle == hmon)
+    }
+
+    /// Re-enumerates monitors after a display change and re-resolves the locked
+    /// monitors. A group lock shrinks to the monitors still present; if none
+    /// are, the lock falls back to the primary monitor.
+    pub fn refresh_monitors(&mut self) -> Refresh {
+        let previous: Vec<MonitorInfo> = self.locked.iter().chain(&self.group)
+            .filter_map(|&h| self.monitors.iter().find(|m| m.handle == h))
+            .cloned()
+            .collect();
+        self.monitors = self.source.monitors();
+        sort_monitors_by(&mut self.monitors, self.order);
+        if self.skip_mirrors {
+            remove_mirrors(&mut self.monitors);
+        }
+        if self.locked.is_none() {
+            // Either nothing is locked or it's a custom rect
+            return Refresh::Unchanged;
+        }
+
+        let mut outcome = None;
+        let mut targets: Vec<&MonitorInfo> = Vec::new();
+        for old in &previous {
+            if let Some(m) = self.find_again(old) {
+                if !targets.iter().any(|t| t.handle == m.handle) {
+                    targets.push(m);
+                }
+            }
+        }
+        if targets.is_empty() {
+            match self.monitors.iter().find(|m| m.primary) {
+                Some(m) => {
+                    outcome = Some(Refresh::FellBackToPrimary(self.lock_rect(m)));
+                    targets.push(m);
+                }
+                None => {
+                    let was_clipped = self.is_clipped();
+                    self.unclip();
+                    self.locked = None;
+                    self.group.clear();
+                    self.region.clear();
+                    self.current_rect = None;
+                    self.state = self.state.after(Transition::Release);
+                    if was_clipped {
+                        self.notify_released();
+                    }
+                    return Refresh::NoMonitors;
+                }
+            }
+        }
+        let handles: Vec<HMONITOR> = targets.iter().map(|m| m.handle).collect();
+        let rects: Vec<RECT> = targets.iter().map(|m| self.lock_rect(m)).collect();
+        let (new_rc, region) = group_shape(&rects);
+        self.locked = Some(handles[0]);
+        self.group = handles[1..].to_vec();
+        self.region = region;
+
+        if outcome.is_none() && self.current_rect.is_some_and(|cur| rects_equal(&cur, &new_rc)) {
+            return Refresh::Unchanged;
+        }
+        self.current_rect = Some(new_rc);
+        if self.is_clipped() {
+            self.clip_failing = self.engage(&new_rc, &self.region).is_err();
+        }
+        self.notify(LockEvent::Switched { monitors: self.current_monitors(), rect: new_rc });
+        outcome.unwrap_or(Refresh::Moved(new_rc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT { left, top, right, bottom }
+    }
+
+    fn pt(x: i32, y: i32) -> POINT {
+        POINT { x, y }
+    }
+
+    fn assert_rect(actual: RECT, expected: RECT) {
+        assert!(
+            rects_equal(&actual, &expected),
+            "expected ({}, {}, {}, {}), got ({}, {}, {}, {})",
+            expected.left, expected.top, expected.right, expected.bottom,
+            actual.left, actual.top, actual.right, actual.bottom,
+        );
+    }
+
+    /// A portrait monitor left of the primary with a negative origin, the
+    /// primary with a taskbar, and one more on the right past a 100px gap.
+    /// Listed out of order, as enumeration doesn't sort either.
+    fn layout() -> Vec<MonitorInfo> {
+        let primary = rect(0, 0, 2560, 1440);
+        let left = rect(-1080, -420, 0, 1500);
+        let right = rect(2660, 0, 4580, 1080);
+        vec![
+            MonitorInfo::new(1 as HMONITOR, primary, rect(0, 0, 2560, 1400), r"\\.\DISPLAY1", true),
+            MonitorInfo::new(3 as HMONITOR, right, right, r"\\.\DISPLAY3", false),
+            MonitorInfo::new(2 as HMONITOR, left, left, r"\\.\DISPLAY2", false),
+        ]
+    }
+
+    #[test]
+    fn engage_locks_from_any_enabled_state() {
+        for state in [LockState::Locked, LockState::ReleasedPending, LockState::Released] {
+            assert_eq!(state.after(Transition::Engage), LockState::Locked);
+        }
+    }
+
+    #[test]
+    fn arm_only_applies_while_held() {
+        assert_eq!(LockState::Locked.after(Transition::Arm), LockState::ReleasedPending);
+        assert_eq!(LockState::ReleasedPending.after(Transition::Arm), LockState::ReleasedPending);
+        assert_eq!(LockState::Released.after(Transition::Arm), LockState::Released);
+    }
+
+    #[test]
+    fn release_clears_a_pending_release() {
+        for state in [LockState::Locked, LockState::ReleasedPending, LockState::Released] {
+            assert_eq!(state.after(Transition::Release), LockState::Released);
+        }
+    }
+
+    #[test]
+    fn disabled_ignores_everything_but_enable() {
+        for transition in [Transition::Engage, Transition::Arm, Transition::Release, Transition::Disable] {
+            assert_eq!(LockState::Disabled.after(transition), LockState::Disabled);
+        }
+        // Enabling alone doesn't clip; the locker engages afterwards
+        assert_eq!(LockState::Disabled.after(Transition::Enable), LockState::Released);
+    }
+
+    #[test]
+    fn disable_and_enable_from_enabled_states() {
+        for state in [LockState::Locked, LockState::ReleasedPending, LockState::Released] {
+            assert_eq!(state.after(Transition::Disable), LockState::Disabled);
+            assert_eq!(state.after(Transition::Enable), state);
+        }
+    }
+
+    #[test]
+    fn every_state_stays_releasable_or_relockable() {
+        // Whatever happens, a held cursor can be armed and a free one engaged
+        let all = [Transition::Engage, Transition::Arm, Transition::Release, Transition::Disable, Transition::Enable];
+        for start in [LockState::Locked, LockState::ReleasedPending, LockState::Released, LockState::Disabled] {
+            for &first in &all {
+                for &second in &all {
+                    let state = start.after(first).after(second);
+                    match state {
+                        LockState::Locked | LockState::ReleasedPending => {
+                            assert!(state.is_clipped());
+                            assert_eq!(state.after(Transition::Arm), LockState::ReleasedPending);
+                        }
+                        LockState::Released => assert_eq!(state.after(Transition::Engage), LockState::Locked),
+                        LockState::Disabled => assert_eq!(state.after(Transition::Enable), LockState::Released),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn new_locker_is_released_and_ignores_arming() {
+        let mut locker = MonitorLocker::with_source(layout());
+        assert_eq!(locker.state(), LockState::Released);
+        locker.arm_release();
+        assert!(!locker.is_release_armed());
+        assert!(locker.is_enabled());
+    }
+
+    #[test]
+    fn confine_follows_shape_and_axis() {
+        let mut locker = MonitorLocker::with_source(layout());
+        let rc = rect(0, 0, 2560, 1440);
+        let p = locker.confine(&pt(3000, 2000), &rc, &[]);
+        assert_eq!((p.x, p.y), (2559, 1439));
+        locker.set_lock_axis(LockAxis::X);
+        let p = locker.confine(&pt(3000, 2000), &rc, &[]);
+        assert_eq!((p.x, p.y), (2559, 2000));
+        locker.set_lock_axis(LockAxis::Both);
+        locker.set_shape(LockShape::Circle);
+        // The corner is outside the circle; the center stays put
+        let p = locker.confine(&pt(0, 0), &rc, &[]);
+        assert!(p.x > 0 && p.y > 0);
+        let p = locker.confine(&pt(1280, 720), &rc, &[]);
+        assert_eq!((p.x, p.y), (1280, 720));
+    }
+
+    #[test]
+    fn contains_is_false_while_free() {
+        let locker = MonitorLocker::with_source(layout());
+        assert!(!locker.contains(100, 100));
+    }
+
+    #[test]
+    fn fake_layout_is_listed_left_to_right() {
+        let locker = MonitorLocker::with_source(layout());
+        let names: Vec<&str> = locker.monitors().iter().map(|m| m.device_name()).collect();
+        assert_eq!(names, [r"\\.\DISPLAY2", r"\\.\DISPLAY1", r"\\.\DISPLAY3"]);
+        assert_eq!(get_primary_monitor_index(locker.monitors()), Some(1));
+    }
+
+    #[test]
+    fn monitor_at_with_negative_origin_and_gap() {
+        let locker = MonitorLocker::with_source(layout());
+        assert_eq!(locker.monitor_at(&pt(-1080, -420)), Some(0));
+        assert_eq!(locker.monitor_at(&pt(-1, 1499)), Some(0));
+        assert_eq!(locker.monitor_at(&pt(0, 0)), Some(1));
+        assert_eq!(locker.monitor_at(&pt(2600, 500)), None);
+        assert_eq!(locker.monitor_at(&pt(2660, 0)), Some(2));
+        // Below the primary, beside the taller left monitor
+        assert_eq!(locker.monitor_at(&pt(100, 1450)), None);
+    }
+
+    #[test]
+    fn lock_rect_applies_work_area_and_insets() {
+        let mut locker = MonitorLocker::with_source(layout());
+        let primary = locker.monitors()[1].clone();
+        assert_rect(locker.lock_rect(&primary), rect(0, 0, 2560, 1440));
+        locker.set_work_area(true);
+        assert_rect(locker.lock_rect(&primary), rect(0, 0, 2560, 1400));
+        locker.set_insets(Insets { left: 10, top: 0, right: 60, bottom: 0 });
+        assert_rect(locker.lock_rect(&primary), rect(10, 0, 2500, 1400));
+        let left = locker.monitors()[0].clone();
+        assert_rect(locker.lock_rect(&left), rect(-1070, -420, -60, 1500));
+    }
+
+    #[test]
+    fn lock_rect_override_by_device_name() {
+        let mut locker = MonitorLocker::with_source(layout());
+        locker.set_work_area(true);
+        let mut overrides = HashMap::new();
+        overrides.insert(r"\\.\display1".to_string(), MonitorOverride {
+            work_area: Some(false),
+            insets: Some(Insets { top: 20, ..Insets::default() }),
+            ..MonitorOverride::default()
+        });
+        locker.set_monitor_overrides(overrides);
+        let primary = locker.monitors()[1].clone();
+        assert_rect(locker.lock_rect(&primary), rect(0, 20, 2560, 1440));
+        // The others keep the global settings
+        let right = locker.monitors()[2].clone();
+        assert_rect(locker.lock_rect(&right), rect(2660, 0, 4580, 1080));
+    }
+
+    #[test]
+    fn group_of_whole_layout_keeps_gaps_out() {
+        let locker = MonitorLocker::with_source(layout());
+        let rects: Vec<RECT> = locker.monitors().iter().map(|m| locker.lock_rect(m)).collect();
+        let (bounds, region) = group_shape(&rects);
+        assert_rect(bounds, rect(-1080, -420, 4580, 1500));
+        assert_eq!(region.len(), 3);
+        // In the gap, closer to the right monitor than the primary
+        let p = clamp_point_to_region(&pt(2620, 500), &region);
+        assert_eq!((p.x, p.y), (2660, 500));
+        // Above the primary, pulled down onto it
+        let p = clamp_point_to_region(&pt(1000, -300), &region);
+        assert_eq!((p.x, p.y), (1000, 0));
+        // On the left monitor, left alone
+        let p = clamp_point_to_region(&pt(-500, -400), &region);
+        assert_eq!((p.x, p.y), (-500, -400));
+    }
+}
@@ -0,0 +1,103 @@
+//! Status messages, appended with a timestamp to `lockmouse.log` next to the
+//! executable and optionally echoed to the console.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use winapi::um::minwinbase::SYSTEMTIME;
+use winapi::um::sysinfoapi::GetLocalTime;
+
+const LOG_FILE_NAME: &str = "lockmouse.log";
+/// Once the log grows past this it's moved to `lockmouse.log.1` at startup,
+/// replacing the previous one.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+/// Echo every message to the console, not just notices.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+/// Print `EVENT` lines for scripts, see [`event`].
+static EVENTS: AtomicBool = AtomicBool::new(false);
+/// Cleared once the console is gone or shouldn't be written to at all.
+static CONSOLE: AtomicBool = AtomicBool::new(true);
+
+/// Logs a state change; shown on the console only with `--verbose`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::logging::write(&format!($($arg)*), false)
+    };
+}
+
+/// Prints a machine-readable `EVENT ...` line on stdout, with `--events`.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        $crate::logging::event(&format!($($arg)*))
+    };
+}
+
+/// Logs something the user should see even without `--verbose`, like
+/// warnings and the initial lock.
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        $crate::logging::write(&format!($($arg)*), true)
+    };
+}
+
+fn log_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(LOG_FILE_NAME))
+}
+
+/// Opens the log file, rotating it first if it's grown too large. Until this
+/// succeeds, messages only go to the console.
+pub fn init(verbose: bool) -> io::Result<PathBuf> {
+    VERBOSE.store(verbose, Ordering::SeqCst);
+    let path = log_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no executable directory"))?;
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_SIZE) {
+        fs::rename(&path, path.with_extension("log.1"))?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(path)
+}
+
+/// Stops writing to the console at all, e.g. once it has been detached.
+pub fn disable_console() {
+    CONSOLE.store(false, Ordering::SeqCst);
+}
+
+fn timestamp() -> String {
+    let mut t: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    unsafe { GetLocalTime(&mut t) };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond, t.wMilliseconds)
+}
+
+pub fn write(message: &str, important: bool) {
+    let mut file = LOG_FILE.lock().unwrap();
+    if let Some(f) = file.as_mut() {
+        // Nowhere else to report a failed log write
+        let _ = writeln!(f, "{} {}", timestamp(), message);
+    }
+    // Without a log file the console is the only record
+    let wanted = file.is_none() || important || VERBOSE.load(Ordering::SeqCst);
+    if wanted && CONSOLE.load(Ordering::SeqCst) {
+        println!("{}", message);
+    }
+}
+
+pub fn enable_events() {
+    EVENTS.store(true, Ordering::SeqCst);
+}
+
+/// One line per state change, in a fixed format: `EVENT <name> [key=value ...]`.
+/// Goes to stdout even without a console, so a wrapper reading a pipe still
+/// gets it.
+pub fn event(line: &str) {
+    if EVENTS.load(Ordering::SeqCst) {
+        println!("EVENT {}", line);
+        // Scripts read these as they happen, not when the buffer fills
+        let _ = io::stdout().flush();
+    }
+}
@@ -1,222 +1,2265 @@
-use std::{ptr, thread, time::Duration};
-use winapi::shared::minwindef::BOOL;
-use winapi::shared::windef::{POINT, RECT, HMONITOR, HDC};
+#[macro_use]
+mod logging;
+mod args;
+mod config;
+mod hotkey;
+mod http;
+mod identify;
+mod keyhook;
+mod overlay;
+mod pipe;
+mod service;
+mod tray;
+mod window;
+
+use args::{Args, MonitorSelector, USAGE};
+use config::Config;
+use serde::Serialize;
+use hotkey::{key_down, Hotkey, KeyEdge, Modifiers};
+use keyhook::KeyHook;
+use overlay::Overlay;
+use lockmousetomonitor::{get_all_monitors_by, get_current_monitor_index, get_primary_monitor_index, mirror_of, rects_equal, remove_mirrors, CursorUpdate, Edge, LockAxis, LockError, MonitorInfo, MonitorLocker, Reapply, Refresh};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+use tray::{MenuItem, Tray, WM_APP_TRAY};
+use window::is_desktop_window;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPARAM, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::windef::{POINT, RECT, HWND};
+use winapi::um::winuser::{
+    GetCursorPos, ClipCursor, VK_CONTROL, VK_F10, VK_F11, VK_LBUTTON, VK_LEFT, VK_LMENU, VK_NUMPAD0, VK_RIGHT,
+    RegisterHotKey, UnregisterHotKey, SetTimer, GetMessageW, TranslateMessage, DispatchMessageW,
+    MSG, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, WM_HOTKEY, WM_TIMER, RegisterClassW, CreateWindowExW, DefWindowProcW,
+    PostMessageW, WNDCLASSW, WM_APP, WM_DISPLAYCHANGE, GetForegroundWindow, GetWindowRect, IsIconic,
+    WM_CONTEXTMENU, WM_RBUTTONUP, PostThreadMessageW, GetShellWindow, MonitorFromWindow, MONITOR_DEFAULTTONULL,
+    OpenInputDesktop, CloseDesktop, DESKTOP_SWITCHDESKTOP, EnumWindows, GetWindowLongW, IsWindowVisible,
+    GWL_EXSTYLE, WS_EX_TRANSPARENT, WM_NULL,
+};
+use winapi::shared::winerror::ERROR_ALREADY_EXISTS;
 use winapi::um::winuser::{
-    GetCursorPos, ClipCursor, MonitorFromPoint, GetMonitorInfoW, MONITORINFO,
-    MONITOR_DEFAULTTONEAREST, GetAsyncKeyState, VK_CONTROL, VK_F11, VK_LMENU, EnumDisplayMonitors,
+    WM_WTSSESSION_CHANGE, WTS_CONSOLE_CONNECT, WTS_CONSOLE_DISCONNECT, WTS_REMOTE_CONNECT,
+    WTS_REMOTE_DISCONNECT, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
 };
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::synchapi::{CreateEventW, CreateMutexW, SetEvent, WaitForSingleObject};
+use winapi::um::utilapiset::Beep;
+use winapi::um::winnt::HANDLE;
+use winapi::um::consoleapi::SetConsoleCtrlHandler;
+use winapi::um::wincon::FreeConsole;
+use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT};
 
-#[derive(Clone)]
-struct MonitorInfo {
-    handle: HMONITOR,
-    rect: RECT,
-}
-
-unsafe extern "system" fn monitor_enum_proc(
-    hmonitor: HMONITOR,
-    _: HDC,
-    _: *mut RECT,
-    data: isize,
-) -> BOOL {
-    let monitors = &mut *(data as *mut Vec<MonitorInfo>);
-    let mut mi: MONITORINFO = std::mem::zeroed();
-    mi.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
-    
-    if GetMonitorInfoW(hmonitor, &mut mi) != 0 {
-        monitors.push(MonitorInfo {
-            handle: hmonitor,
-            rect: mi.rcMonitor,
-        });
+/// Interval of the poll timer. `SetTimer` fires on the system clock tick
+/// (15.6ms by default), so asking for 16ms would round up to two ticks and
+/// give ~32Hz; 15ms lands on every tick for ~64Hz.
+const POLL_INTERVAL_MS: u32 = 15;
+/// The slower interval once nothing has happened for `IDLE_POLL_AFTER`, to
+/// save wakeups on battery.
+const IDLE_POLL_INTERVAL_MS: u32 = 100;
+const IDLE_POLL_AFTER: Duration = Duration::from_secs(3);
+/// After this many ticks in a row without a cursor position the failure is
+/// logged and the timer backs off to `CURSOR_FAILURE_POLL_MS` until one works.
+const CURSOR_FAILURE_LIMIT: u32 = 50;
+const CURSOR_FAILURE_POLL_MS: u32 = 500;
+
+/// How often and how far apart to re-enumerate when no monitors are found
+/// at startup.
+const MONITOR_RETRIES: u32 = 5;
+const MONITOR_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How long `--identify` shows the numbers.
+const IDENTIFY_DURATION: Duration = Duration::from_secs(3);
+
+/// Invalid answers to the monitor prompt before giving up on it, in case
+/// stdin is something that never produces a valid line.
+const MAX_PROMPT_ATTEMPTS: u32 = 5;
+
+/// After the pick hotkey releases the lock, the monitor the cursor rests on
+/// this long gets it; before the first movement it gets `PICK_FIRST_MOVE`.
+const PICK_SETTLE: Duration = Duration::from_millis(600);
+const PICK_FIRST_MOVE: Duration = Duration::from_secs(3);
+
+/// With `--drag-widen`, how long the left button has to be held with the
+/// cursor at an edge before that edge opens.
+const DRAG_EDGE_HOLD: Duration = Duration::from_millis(300);
+
+/// This many stolen clips within `STEAL_BURST_WINDOW` get a tray notification
+/// naming the program in front, as something is fighting over the cursor.
+const STEAL_BURST_COUNT: u32 = 5;
+const STEAL_BURST_WINDOW: Duration = Duration::from_secs(10);
+
+/// How far a window's edges may be from its monitor's and still count as
+/// fullscreen, for borderless windows that are off by a pixel.
+const FULLSCREEN_TOLERANCE: i32 = 2;
+
+/// Default for how quickly the second press of a double-tap release has to follow.
+const DEFAULT_DOUBLE_TAP_MS: u64 = 400;
+
+/// Tones for `--sound`, as (frequency in Hz, duration in ms): a rising one
+/// for locking and a lower one for releasing.
+const LOCK_TONE: (u32, u32) = (880, 60);
+const RELEASE_TONE: (u32, u32) = (440, 60);
+
+/// Number of ticks to time at startup before reporting the measured rate.
+const RATE_SAMPLE_TICKS: u32 = 64;
+
+// `RegisterHotKey` ids
+const HOTKEY_SWITCH: i32 = 1;
+const HOTKEY_TOGGLE: i32 = 2;
+const HOTKEY_NEXT_MONITOR: i32 = 3;
+const HOTKEY_PREV_MONITOR: i32 = 4;
+const HOTKEY_NEXT_PROFILE: i32 = 5;
+const HOTKEY_FAVORITE: i32 = 6;
+const HOTKEY_RELOAD: i32 = 7;
+const HOTKEY_TIMED_RELEASE: i32 = 8;
+const HOTKEY_PICK_MONITOR: i32 = 9;
+/// Plus the monitor number, for the keypad jumps to monitors 1-9.
+const HOTKEY_JUMP_BASE: i32 = 10;
+/// Past the keypad jumps.
+const HOTKEY_STATUS: i32 = 20;
+
+/// Either Ctrl, or the left Alt.
+const DEFAULT_RELEASE_KEYS: [i32; 2] = [VK_CONTROL, VK_LMENU];
+
+const DEFAULT_SWITCH_HOTKEY: Hotkey = Hotkey::new(0, VK_F11);
+const DEFAULT_TOGGLE_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, b'L' as i32);
+const DEFAULT_NEXT_MONITOR_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, VK_RIGHT);
+const DEFAULT_PREV_MONITOR_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, VK_LEFT);
+const DEFAULT_PROFILE_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, b'P' as i32);
+const DEFAULT_FAVORITE_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, b'F' as i32);
+const DEFAULT_RELOAD_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, b'R' as i32);
+const DEFAULT_TIMED_RELEASE_HOTKEY: Hotkey = Hotkey::new(0, VK_F10);
+const DEFAULT_PICK_MONITOR_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL, VK_F11);
+const DEFAULT_STATUS_HOTKEY: Hotkey = Hotkey::new(MOD_CONTROL | MOD_ALT, b'S' as i32);
+const DEFAULT_JUMP_MODIFIERS: Modifiers = Modifiers((MOD_CONTROL | MOD_ALT) as u32);
+
+// winapi 0.3 doesn't bind the session notification calls.
+#[link(name = "wtsapi32")]
+extern "system" {
+    fn WTSRegisterSessionNotification(hwnd: HWND, flags: DWORD) -> BOOL;
+    fn WTSUnRegisterSessionNotification(hwnd: HWND) -> BOOL;
+}
+const NOTIFY_FOR_THIS_SESSION: DWORD = 0;
+
+/// Posted by the window procedure when the display layout changes.
+const WM_APP_DISPLAY_CHANGED: UINT = WM_APP + 1;
+/// Posted by the window procedure for `WM_WTSSESSION_CHANGE`, with the
+/// `WTS_*` event as the wParam.
+const WM_APP_SESSION_CHANGED: UINT = WM_APP + 5;
+/// Posted by the window procedure when the tray icon is right-clicked.
+const WM_APP_TRAY_MENU: UINT = WM_APP + 3;
+/// Posted to the main thread by the pipe or HTTP server when a command is
+/// waiting.
+const WM_APP_PIPE_COMMAND: UINT = WM_APP + 4;
+
+// Tray menu ids; monitor entries are `TRAY_MONITOR_BASE + index`
+const TRAY_TOGGLE: u32 = 1;
+const TRAY_QUIT: u32 = 2;
+const TRAY_MONITOR_BASE: u32 = 100;
+
+/// Name of the mutex that keeps a second instance from starting.
+const INSTANCE_MUTEX_NAME: &str = "Local\\LockMouseToMonitor.Instance";
+
+/// Set by the console control handler; the main loop exits when it sees it.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// The thread running the message loop, woken by [`request_shutdown`].
+static MAIN_THREAD: AtomicU32 = AtomicU32::new(0);
+
+/// Event the message loop sets once it has left the loop and released the
+/// lock, for the control handler to wait on.
+static RELEASED: AtomicPtr<winapi::ctypes::c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// How long the control handler waits for the loop to release the lock.
+/// Windows ends the process about 5 seconds after a console close.
+const SHUTDOWN_WAIT_MS: DWORD = 2000;
+
+/// The locker's saved clip, for the control handler to restore on its own.
+static SAVED_CLIP: Mutex<Option<RECT>> = Mutex::new(None);
+
+/// Asks the message loop to exit, from any thread, waking it rather than
+/// waiting for its next timer tick.
+fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    let thread_id = MAIN_THREAD.load(Ordering::SeqCst);
+    if thread_id != 0 {
+        unsafe { PostThreadMessageW(thread_id, WM_NULL, 0, 0) };
     }
-    1 // continue enumeration
 }
 
-fn get_all_monitors() -> Vec<MonitorInfo> {
-    let mut monitors = Vec::new();
-    let monitors_ptr = &mut monitors as *mut Vec<MonitorInfo>;
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: DWORD) -> BOOL {
+    match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT => {
+            request_shutdown();
+            // On console close the process is terminated as soon as this
+            // handler returns, so release here too, once the loop has stopped
+            // and can't re-apply the clip behind our back
+            let released = RELEASED.load(Ordering::SeqCst);
+            if !released.is_null() {
+                WaitForSingleObject(released, SHUTDOWN_WAIT_MS);
+            }
+            let saved = *SAVED_CLIP.lock().unwrap_or_else(|e| e.into_inner());
+            ClipCursor(saved.as_ref().map_or(ptr::null(), |rc| rc as *const RECT));
+            TRUE
+        }
+        _ => FALSE,
+    }
+}
+
+unsafe extern "system" fn window_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_DISPLAYCHANGE => {
+            // Hand it to the message loop, which owns the lock state
+            PostMessageW(hwnd, WM_APP_DISPLAY_CHANGED, 0, 0);
+            0
+        }
+        WM_WTSSESSION_CHANGE => {
+            PostMessageW(hwnd, WM_APP_SESSION_CHANGED, wparam, 0);
+            0
+        }
+        WM_APP_TRAY => {
+            let event = lparam as UINT;
+            if event == WM_RBUTTONUP || event == WM_CONTEXTMENU {
+                PostMessageW(hwnd, WM_APP_TRAY_MENU, 0, 0);
+            }
+            0
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+/// Creates the hidden window that receives broadcast messages like
+/// `WM_DISPLAYCHANGE`. It has to be a real top-level window: message-only
+/// windows don't get broadcasts. It is never shown.
+fn create_message_window() -> Option<HWND> {
+    let class_name: Vec<u16> = "LockMouseToMonitor\0".encode_utf16().collect();
     unsafe {
-        EnumDisplayMonitors(
+        let instance = GetModuleHandleW(ptr::null());
+        let mut wc: WNDCLASSW = std::mem::zeroed();
+        wc.lpfnWndProc = Some(window_proc);
+        wc.hInstance = instance;
+        wc.lpszClassName = class_name.as_ptr();
+        if RegisterClassW(&wc) == 0 {
+            return None;
+        }
+        let hwnd = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0, 0, 0, 0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
             ptr::null_mut(),
-            ptr::null(),
-            Some(monitor_enum_proc),
-            monitors_ptr as isize,
         );
+        if hwnd.is_null() { None } else { Some(hwnd) }
+    }
+}
+
+/// Whether this process can open the desktop that gets the input. It can't
+/// while the secure desktop is up, as only SYSTEM may open that one.
+fn input_desktop_accessible() -> bool {
+    let desktop = unsafe { OpenInputDesktop(0, FALSE, DESKTOP_SWITCHDESKTOP) };
+    if desktop.is_null() {
+        return false;
+    }
+    unsafe { CloseDesktop(desktop) };
+    true
+}
+
+/// The running program: the locker plus the input and config state around it.
+struct App {
+    locker: MonitorLocker,
+    config: Config,
+    /// Clip to the foreground window instead of a monitor.
+    lock_window: bool,
+    /// The window `lock_window` mode last clipped to.
+    followed_window: HWND,
+    /// Warp the cursor to the middle of the monitor after F11 and the initial lock.
+    center_on_switch: bool,
+    /// `--warp-in`: like `center_on_switch`, but only when the cursor was
+    /// outside the new lock.
+    warp_in: bool,
+    /// Move the lock to the monitor a fullscreen window appears on.
+    follow_fullscreen: bool,
+    /// `--game-mode`: hold the cursor as hard as possible while a fullscreen
+    /// window is on the locked monitor.
+    game_mode: bool,
+    /// Started with `--lock-all`: display changes keep every monitor in the
+    /// lock, as long as it hasn't been switched to fewer since.
+    lock_all: bool,
+    /// The fullscreen window last followed, so the lock moves once per window
+    /// rather than fighting an F11 switch away from it.
+    fullscreen_window: HWND,
+    /// Processes or window classes that suspend the lock while they have focus.
+    pause_for: Vec<String>,
+    /// The foreground window last checked against `pause_for`.
+    checked_window: HWND,
+    /// Set while `pause_for` has disabled the lock, so focus moving away turns
+    /// it back on (but doesn't override the toggle hotkey).
+    paused_for_app: bool,
+    /// Virtual-key codes that arm the edge release; any one of them will do.
+    release_keys: Vec<i32>,
+    release_key: KeyEdge,
+    /// Free the cursor only while a release key is held, instead of arming
+    /// the edge release.
+    hold_release: bool,
+    /// Set while a held release key has the clip suspended.
+    holding_release: bool,
+    /// With a double-tap release, the longest gap between the two presses.
+    double_tap: Option<Duration>,
+    /// The first press of a possible double tap.
+    last_release_tap: Option<Instant>,
+    /// Bring the cursor back if it stays off the locked monitor this long
+    /// after an edge release.
+    relock_after: Option<Duration>,
+    /// When the edge release last let the cursor go.
+    released_at: Option<Instant>,
+    /// Release the lock after the cursor has been still this long.
+    idle_release: Option<Duration>,
+    /// Where the previous tick left the cursor, after any warps of its own,
+    /// and when it was last seen somewhere else.
+    last_cursor: POINT,
+    last_moved: Instant,
+    /// Released for being idle; the next movement re-locks.
+    idle_released: bool,
+    /// `--once`: the first edge release ends the program.
+    once: bool,
+    /// How long the timed-release hotkey frees the cursor for, and when the
+    /// current timed release runs out.
+    timed_release: Option<Duration>,
+    timed_release_until: Option<Instant>,
+    /// While picking a monitor with Ctrl+F11, when the cursor will have
+    /// rested long enough to lock to the monitor under it.
+    pick_deadline: Option<Instant>,
+    /// `--drag-widen`: open the edge a window is being dragged against.
+    drag_widen: bool,
+    /// The edge the cursor has been at with the left button held, and since when.
+    drag_at_edge: Option<(Edge, Instant)>,
+    /// The secure desktop (Win+L, UAC, Ctrl+Alt+Del) has the input, so
+    /// `ClipCursor` can only fail and the tick does nothing.
+    on_secure_desktop: bool,
+    /// Hotkeys `RegisterHotKey` refused (usually because another program has
+    /// them), polled on each tick instead.
+    polled_hotkeys: Vec<(i32, Hotkey, KeyEdge)>,
+    /// Start of the startup rate measurement, cleared once it's been reported.
+    rate_sample_start: Option<Instant>,
+    ticks: u32,
+    tray: Option<Tray>,
+    /// `--overlay`: the border around the locked monitor.
+    overlay: Option<Overlay>,
+    /// `--input-hook`: key state from the keyboard hook instead of
+    /// `GetAsyncKeyState`, and all hotkeys are read from it.
+    key_hook: Option<KeyHook>,
+    /// The config profile in effect, if any.
+    active_profile: Option<String>,
+    /// Show a notification when the lock engages, releases or moves.
+    toast: bool,
+    /// Which `--sound` cues are on.
+    lock_sound: bool,
+    release_sound: bool,
+    /// How often the clip was found changed by someone else, by the
+    /// foreground program at the time.
+    clip_steals: HashMap<String, u32>,
+    /// Stolen clips since the lock last moved, for the tray tooltip, with the
+    /// monitors they were counted on.
+    reapplies: (Vec<usize>, u32),
+    /// Start and count of the current run of stolen clips.
+    steal_burst: Option<(Instant, u32)>,
+    /// The fast and idle poll intervals in milliseconds, the one the timer
+    /// runs at now, and the timer's id for changing it.
+    poll_intervals: (u32, u32),
+    poll_interval: u32,
+    timer_id: usize,
+    /// Ticks in a row that `GetCursorPos` failed on.
+    cursor_failures: u32,
+    /// Last cursor movement, key press or hotkey, for the idle interval.
+    last_active: Instant,
+    /// Clip state at the end of the last tick, to catch transitions.
+    was_clipped: bool,
+    /// Commands from the control pipe and the HTTP endpoint, if either is
+    /// enabled.
+    pipe_requests: Option<Receiver<pipe::Request>>,
+}
+
+impl App {
+    /// Periodic check of the cursor and release keys.
+    fn tick(&mut self) {
+        self.measure_rate();
+        if self.check_secure_desktop() {
+            return;
+        }
+        if self.lock_window {
+            self.follow_foreground_window();
+        } else if self.follow_fullscreen {
+            self.follow_fullscreen_window();
+        }
+        if !self.pause_for.is_empty() {
+            self.check_paused_app();
+        }
+        if self.game_mode {
+            self.check_game_mode();
+        }
+
+        let mut pt: POINT = unsafe { std::mem::zeroed() };
+        if unsafe { GetCursorPos(&mut pt) } == 0 {
+            self.cursor_pos_failed();
+            return;
+        }
+        if self.cursor_failures >= CURSOR_FAILURE_LIMIT {
+            // The end of this tick puts the poll interval back
+            status!("GetCursorPos works again after {} failed ticks", self.cursor_failures);
+        }
+        self.cursor_failures = 0;
+
+        if let Some(refresh) = self.locker.check_stale_rect(&pt) {
+            // The cursor got past the monitor's real edge, so its resolution
+            // shrank without a display change being handled yet
+            self.report_refresh(refresh);
+        }
+        let moved = pt.x != self.last_cursor.x || pt.y != self.last_cursor.y;
+        self.check_idle(moved);
+
+        if let Some(hook) = &mut self.key_hook {
+            hook.poll();
+        }
+        let (release_key_pressed, release_key_edge) = match &self.key_hook {
+            // The hook catches presses that came and went between ticks
+            Some(hook) => (
+                self.release_keys.iter().any(|&vk| hook.is_down(vk)),
+                self.release_keys.iter().any(|&vk| hook.was_pressed(vk)),
+            ),
+            None => {
+                let down = self.release_keys.iter().any(|&vk| key_down(vk));
+                (down, self.release_key.pressed(down))
+            }
+        };
+
+        // Put the clip back if something (alt-tab, another program) changed it
+        match self.locker.reapply() {
+            Reapply::Failed(code) => status!("ClipCursor failed (error {}); retrying until it sticks", code),
+            Reapply::Recovered => status!("Clip re-applied"),
+            Reapply::Restored => self.report_stolen_clip(),
+            Reapply::Unchanged => {}
+        }
+
+        if moved || release_key_pressed {
+            self.last_active = Instant::now();
+        }
+
+        if self.hold_release {
+            self.hold_to_release(release_key_pressed);
+        } else if release_key_edge && self.locker.is_enabled() && self.release_tapped() {
+            // Release key-down event; arming does nothing while already released
+            self.locker.arm_release();
+            if self.locker.is_release_armed() {
+                status!("Release key pressed: will release the clip the next time the cursor hits the monitor edge");
+            }
+        }
+
+        let mut fired = Vec::new();
+        for (id, hotkey, edge) in &mut self.polled_hotkeys {
+            let pressed = match &self.key_hook {
+                Some(hook) => hook.hotkey_pressed(hotkey),
+                None => edge.pressed(hotkey.is_down()),
+            };
+            if pressed {
+                fired.push(*id);
+            }
+        }
+        for id in fired {
+            self.on_hotkey(id);
+        }
+
+        // Handle monitor edge detection and release. While a held release key
+        // has the cursor free, returning to the monitor mustn't re-lock it
+        let update = if self.holding_release
+            || self.idle_released
+            || self.timed_release_until.is_some()
+            || self.pick_deadline.is_some()
+        {
+            CursorUpdate::Unchanged
+        } else {
+            self.locker.update(&pt)
+        };
+        match update {
+            CursorUpdate::Released if self.once => {
+                status!("Released clip; exiting (--once)");
+                event!("released");
+                SHUTDOWN.store(true, Ordering::SeqCst);
+            }
+            CursorUpdate::Released => {
+                status!("Released clip – you can move to other monitors now");
+                event!("released");
+                self.toast("Released: the cursor can leave the monitor");
+                self.released_at = Some(Instant::now());
+            }
+            CursorUpdate::Relocked => {
+                status!("Cursor returned to monitor; re-locked");
+                self.lock_event();
+                self.toast(&format!("Locked to {}", self.monitor_description()));
+                self.released_at = None;
+            }
+            CursorUpdate::Unchanged => {}
+        }
+        self.check_relock_timeout();
+        self.check_timed_release();
+        self.check_monitor_pick(&pt, moved);
+        if self.drag_widen {
+            self.check_drag(&pt);
+        }
+        // Re-read rather than keep `pt`, so a warp from this tick (a soft lock
+        // or a re-lock pulling the cursor in) doesn't look like movement
+        if unsafe { GetCursorPos(&mut pt) } != 0 {
+            self.last_cursor = pt;
+        }
+        // Thread messages are lost while the tray menu runs its own loop, so
+        // don't rely on WM_APP_PIPE_COMMAND alone
+        self.handle_pipe_requests();
+        self.play_transition_cue();
+        self.update_tray();
+        self.update_overlay();
+        self.adapt_poll_interval();
+    }
+
+    /// Moves the border to the locked rect, or hides it while released.
+    fn update_overlay(&mut self) {
+        let Some(overlay) = &mut self.overlay else { return };
+        overlay.show(self.locker.current_rect().filter(|_| self.locker.is_clipped()));
+    }
+
+    /// Counts a tick without a cursor position. A long run of them gets one
+    /// warning and a slower timer, so a failure that doesn't go away (it
+    /// happens around the secure desktop) neither spins nor goes unnoticed.
+    fn cursor_pos_failed(&mut self) {
+        self.cursor_failures += 1;
+        if self.cursor_failures != CURSOR_FAILURE_LIMIT {
+            return;
+        }
+        status!("GetCursorPos failed {} ticks in a row (error {}); checking every {}ms until it works again",
+            self.cursor_failures, unsafe { GetLastError() }, CURSOR_FAILURE_POLL_MS);
+        self.poll_interval = CURSOR_FAILURE_POLL_MS;
+        unsafe { SetTimer(ptr::null_mut(), self.timer_id, CURSOR_FAILURE_POLL_MS, None) };
+    }
+
+    /// Slows the timer down once the mouse and keys have been left alone for a
+    /// while, and speeds it back up on the first tick that sees activity.
+    fn adapt_poll_interval(&mut self) {
+        let (fast, slow) = self.poll_intervals;
+        // A game can pull the cursor away while the mouse sits still
+        let busy = self.locker.is_sticky() || self.last_active.elapsed() < IDLE_POLL_AFTER;
+        let wanted = if busy { fast } else { slow };
+        if wanted != self.poll_interval {
+            self.poll_interval = wanted;
+            // The same id replaces the timer rather than adding one
+            unsafe { SetTimer(ptr::null_mut(), self.timer_id, wanted, None) };
+        }
+    }
+
+    /// Logs a clip that something else changed behind our back, with the
+    /// program in the foreground at the time: over a session the counts show
+    /// which program keeps doing it.
+    fn report_stolen_clip(&mut self) {
+        let hwnd = unsafe { GetForegroundWindow() };
+        let culprit = (!hwnd.is_null()).then(|| window::process_name(hwnd)).flatten()
+            .unwrap_or_else(|| "unknown".to_string());
+        let count = self.clip_steals.entry(culprit.clone()).or_insert(0);
+        *count += 1;
+        status!("Clip stolen ({} time{} while {} had focus); re-applied", count, if *count == 1 { "" } else { "s" }, culprit);
+        event!("clip-stolen foreground={} count={}", culprit, count);
+
+        self.reset_reapplies_on_switch();
+        self.reapplies.1 += 1;
+        let burst = match self.steal_burst {
+            Some((start, n)) if start.elapsed() < STEAL_BURST_WINDOW => (start, n + 1),
+            _ => (Instant::now(), 1),
+        };
+        self.steal_burst = Some(burst);
+        if burst.1 == STEAL_BURST_COUNT {
+            if let Some(tray) = &self.tray {
+                tray.notify("LockMouseToMonitor", &format!(
+                    "The clip was taken {} times in {}s, last while {} had focus",
+                    burst.1, STEAL_BURST_WINDOW.as_secs(), culprit));
+            }
+        }
+    }
+
+    /// Beeps when the clip has engaged or dropped since the last tick.
+    fn play_transition_cue(&mut self) {
+        let clipped = self.locker.is_clipped();
+        if clipped == self.was_clipped {
+            return;
+        }
+        self.was_clipped = clipped;
+        let tone = match clipped {
+            true if self.lock_sound => LOCK_TONE,
+            false if self.release_sound => RELEASE_TONE,
+            _ => return,
+        };
+        // Beep blocks for the length of the tone
+        thread::spawn(move || unsafe { Beep(tone.0, tone.1) });
+    }
+
+    fn on_hotkey(&mut self, id: i32) {
+        self.last_active = Instant::now();
+        match id {
+            HOTKEY_SWITCH => self.switch_to_cursor_monitor(),
+            HOTKEY_TOGGLE => self.toggle_enabled(),
+            HOTKEY_NEXT_MONITOR => self.cycle_monitor(1),
+            HOTKEY_PREV_MONITOR => self.cycle_monitor(-1),
+            HOTKEY_NEXT_PROFILE => self.cycle_profile(),
+            HOTKEY_FAVORITE => self.swap_favorite(),
+            HOTKEY_RELOAD => {
+                // A failed reload is logged; there's no one else to tell
+                let _ = self.reload_config();
+            }
+            HOTKEY_TIMED_RELEASE => self.timed_release(),
+            HOTKEY_PICK_MONITOR => self.pick_monitor(),
+            HOTKEY_STATUS => notice!("Status: {}", self.status_line()),
+            id if id > HOTKEY_JUMP_BASE => self.jump_to_monitor((id - HOTKEY_JUMP_BASE - 1) as usize),
+            _ => {}
+        }
+    }
+
+    /// Reports the real tick rate once after startup, so the cadence is visible.
+    fn measure_rate(&mut self) {
+        let Some(start) = self.rate_sample_start else { return };
+        self.ticks += 1;
+        if self.ticks == RATE_SAMPLE_TICKS {
+            let per_tick = start.elapsed() / RATE_SAMPLE_TICKS;
+            status!("Polling every {}ms ({:.1}ms measured, ~{:.0}Hz)",
+                self.poll_intervals.0,
+                per_tick.as_secs_f64() * 1000.0,
+                1.0 / per_tick.as_secs_f64());
+            self.rate_sample_start = None;
+        }
+    }
+
+    /// `--lock-window`: keeps the lock on the foreground window's rect, and
+    /// suspends it while that window is minimized or has no area.
+    fn follow_foreground_window(&mut self) {
+        let hwnd = unsafe { GetForegroundWindow() };
+        let mut rc: RECT = unsafe { std::mem::zeroed() };
+        let usable = !hwnd.is_null()
+            && unsafe { IsIconic(hwnd) } == 0
+            && unsafe { GetWindowRect(hwnd, &mut rc) } != 0
+            && rc.right > rc.left
+            && rc.bottom > rc.top;
+
+        if !usable {
+            if self.locker.current_rect().is_some() {
+                self.locker.unlock();
+                status!("Foreground window is minimized or empty; clip suspended");
+                event!("released");
+            }
+            self.followed_window = ptr::null_mut();
+            return;
+        }
+
+        if hwnd != self.followed_window {
+            self.followed_window = hwnd;
+            if self.locker.lock_to_rect(rc) {
+                status!("Locked to foreground window: left={} top={} right={} bottom={}",
+                    rc.left, rc.top, rc.right, rc.bottom);
+                self.lock_event();
+            }
+        } else if self.locker.is_clipped()
+            && !self.locker.current_rect().is_some_and(|cur| rects_equal(&cur, &rc))
+        {
+            // Same window, moved or resized
+            self.locker.lock_to_rect(rc);
+        }
+    }
+
+    /// `--hold-release`: frees the cursor while a release key is held and
+    /// locks it again the moment the key is let go, wherever the cursor is by
+    /// then.
+    fn hold_to_release(&mut self, down: bool) {
+        if down && !self.holding_release && self.locker.is_enabled() && self.locker.is_clipped() {
+            self.locker.release();
+            self.holding_release = true;
+            status!("Release key held: cursor is free until it's let go");
+            event!("released");
+        } else if !down && self.holding_release {
+            self.holding_release = false;
+            if self.locker.relock() {
+                status!("Release key let go: re-locked");
+                self.lock_event();
+            }
+        }
+    }
+
+    /// Whether a release key press should arm the release. In double-tap mode
+    /// only the second of two quick presses does.
+    fn release_tapped(&mut self) -> bool {
+        let Some(window) = self.double_tap else {
+            return true;
+        };
+        let now = Instant::now();
+        match self.last_release_tap.take() {
+            Some(first) if now - first <= window => true,
+            _ => {
+                self.last_release_tap = Some(now);
+                false
+            }
+        }
+    }
+
+    /// `--idle-release`: lets the cursor go once it's been still for the idle
+    /// period, and locks again on the first movement after that.
+    fn check_idle(&mut self, moved: bool) {
+        let Some(idle) = self.idle_release else { return };
+        if moved {
+            self.last_moved = Instant::now();
+            if self.idle_released {
+                self.idle_released = false;
+                if self.locker.relock() {
+                    status!("Cursor moved; re-locked after being idle");
+                    self.lock_event();
+                }
+            }
+        } else if !self.idle_released
+            && self.locker.is_clipped()
+            && self.locker.is_enabled()
+            && self.last_moved.elapsed() >= idle
+        {
+            self.locker.release();
+            self.idle_released = true;
+            status!("No cursor movement for {}s; released the clip until the mouse moves", idle.as_secs());
+            event!("released");
+        }
+    }
+
+    /// Frees the cursor for `timed_release`; a second press before it runs
+    /// out locks it again early.
+    fn timed_release(&mut self) {
+        let Some(duration) = self.timed_release else { return };
+        if self.timed_release_until.take().is_some() {
+            if self.locker.relock() {
+                status!("Timed release ended early; re-locked");
+                self.lock_event();
+            }
+            return;
+        }
+        if !self.locker.is_enabled() || self.locker.current_rect().is_none() {
+            return;
+        }
+        self.locker.release();
+        self.timed_release_until = Some(Instant::now() + duration);
+        status!("Released the clip for {}s", duration.as_secs());
+        event!("released");
+        self.toast(&format!("Released for {}s", duration.as_secs()));
+    }
+
+    /// Re-locks once a timed release has run out, wherever the cursor is.
+    fn check_timed_release(&mut self) {
+        let Some(until) = self.timed_release_until else { return };
+        if Instant::now() < until {
+            return;
+        }
+        self.timed_release_until = None;
+        if self.locker.relock() {
+            status!("Timed release over; re-locked");
+            self.lock_event();
+            self.toast(&format!("Locked to {}", self.monitor_description()));
+        }
+    }
+
+    /// Warps the cursor back once it's been released for longer than
+    /// `relock_after`; the next tick then re-locks it like any return.
+    fn check_relock_timeout(&mut self) {
+        let (Some(timeout), Some(released_at)) = (self.relock_after, self.released_at) else {
+            return;
+        };
+        if self.locker.is_clipped() || !self.locker.is_enabled() {
+            self.released_at = None;
+        } else if released_at.elapsed() >= timeout {
+            status!("Cursor didn't come back within {}s; returning it to the locked monitor", timeout.as_secs());
+            self.locker.warp_to_center();
+            self.released_at = None;
+        }
+    }
+
+    fn follow_fullscreen_window(&mut self) {
+        let hwnd = unsafe { GetForegroundWindow() };
+        let Some(idx) = fullscreen_monitor(hwnd, self.locker.monitors()) else {
+            self.fullscreen_window = ptr::null_mut();
+            return;
+        };
+        if hwnd == self.fullscreen_window {
+            return;
+        }
+        self.fullscreen_window = hwnd;
+        if !self.locker.current_monitors().contains(&idx) {
+            self.switch_to(idx, "Fullscreen window", self.center_on_switch);
+        }
+    }
+
+    /// Turns sticky locking on while the foreground window is fullscreen on a
+    /// locked monitor, and off again once it isn't.
+    fn check_game_mode(&mut self) {
+        let hwnd = unsafe { GetForegroundWindow() };
+        let fullscreen = fullscreen_monitor(hwnd, self.locker.monitors())
+            .is_some_and(|idx| self.locker.current_monitors().contains(&idx));
+        if fullscreen == self.locker.is_sticky() {
+            return;
+        }
+        self.locker.set_sticky(fullscreen);
+        if fullscreen {
+            let name = window::process_name(hwnd).unwrap_or_else(|| "unknown".to_string());
+            status!("Game mode: {} is fullscreen on the locked monitor; re-applying the clip on every check", name);
+        } else {
+            status!("Game mode: no fullscreen window on the locked monitor any more");
+        }
+    }
+
+    /// F11 handler: move the lock to the monitor under the cursor.
+    fn switch_to_cursor_monitor(&mut self) {
+        let mut pt: POINT = unsafe { std::mem::zeroed() };
+        if unsafe { GetCursorPos(&mut pt) } == 0 {
+            return;
+        }
+        let Some(idx) = self.locker.monitor_at(&pt) else { return };
+        self.switch_or_relock(idx, "F11 pressed", self.center_on_switch);
+    }
+
+    /// Ctrl+F11: F11 for a monitor the clip won't let the cursor reach. The
+    /// lock lets go, and comes back on whichever monitor the cursor settles on.
+    fn pick_monitor(&mut self) {
+        if self.lock_window || !self.locker.is_enabled() {
+            return;
+        }
+        self.locker.release();
+        self.pick_deadline = Some(Instant::now() + PICK_FIRST_MOVE);
+        status!("Picking a monitor: the lock follows the cursor to the monitor it stops on");
+        event!("released");
+    }
+
+    /// Opens the clip on an edge the cursor has been pushed against with the
+    /// left button held, as when dragging a window, and closes it again when
+    /// the button comes up.
+    fn check_drag(&mut self, pt: &POINT) {
+        let held = key_down(VK_LBUTTON);
+        if let Some(edge) = self.locker.widened() {
+            if !held {
+                self.locker.end_widen();
+                status!("Drag ended; {:?} edge closed again", edge);
+            }
+            return;
+        }
+        let edge = self.locker.edge_at(pt).filter(|_| held);
+        match (edge, self.drag_at_edge) {
+            (Some(edge), Some((at, since))) if edge == at => {
+                if since.elapsed() >= DRAG_EDGE_HOLD && self.locker.widen(edge) {
+                    status!("Dragging against the {:?} edge; letting the cursor across until the button is released", edge);
+                    self.drag_at_edge = None;
+                }
+            }
+            (Some(edge), _) => self.drag_at_edge = Some((edge, Instant::now())),
+            (None, _) => self.drag_at_edge = None,
+        }
+    }
+
+    fn check_monitor_pick(&mut self, pt: &POINT, moved: bool) {
+        let Some(deadline) = self.pick_deadline else { return };
+        if self.locker.is_clipped() || !self.locker.is_enabled() {
+            // F11 or something else locked in the meantime
+            self.pick_deadline = None;
+        } else if moved {
+            self.pick_deadline = Some(Instant::now() + PICK_SETTLE);
+        } else if Instant::now() >= deadline {
+            self.pick_deadline = None;
+            match self.locker.monitor_at(pt) {
+                Some(idx) => self.switch_or_relock(idx, "Ctrl+F11", false),
+                None => {
+                    self.locker.relock();
+                }
+            }
+        }
+    }
+
+    /// Keypad hotkey handler: the F11 switch, to monitor `idx` instead of the
+    /// one under the cursor, which is brought along.
+    fn jump_to_monitor(&mut self, idx: usize) {
+        // All nine keys are claimed, whatever is plugged in
+        if idx >= self.locker.monitors().len() {
+            return;
+        }
+        self.switch_or_relock(idx, "Keypad hotkey", true);
+    }
+
+    fn switch_or_relock(&mut self, idx: usize, source: &str, warp: bool) {
+        if self.lock_window {
+            status!("{} has no effect while locked to the foreground window", source);
+            return;
+        }
+        // Locking works from any state, even with nothing locked yet; only
+        // the monitor that's already locked skips the switch message
+        if self.locker.current_monitors().contains(&idx) {
+            let before = cursor_pos();
+            if !self.locker.is_clipped() && self.locker.relock() {
+                if self.warp_in {
+                    warp_in(&self.locker, before);
+                }
+                status!("{}: re-locked to monitor {}", source, idx + 1);
+                self.lock_event();
+            }
+            return;
+        }
+        self.switch_to(idx, source, warp);
+    }
+
+    /// Moves the lock to monitor `idx` and remembers it, for F11 and the tray menu.
+    fn switch_to(&mut self, idx: usize, source: &str, warp: bool) {
+        let before = cursor_pos();
+        if self.locker.lock_to(idx) {
+            if warp {
+                self.locker.warp_to_center();
+            } else if self.warp_in {
+                warp_in(&self.locker, before);
+            }
+            status!("{}: Changed lock to monitor {}", source, idx + 1);
+            self.lock_event();
+            self.toast(&format!("Switched to {}", self.monitor_description()));
+            self.config.monitor = Some(idx + 1);
+            save_config(&self.config);
+        }
+    }
+
+    /// Moves the lock `step` monitors along the sorted list, wrapping at the
+    /// ends, and brings the cursor along to the new monitor's center.
+    fn cycle_monitor(&mut self, step: isize) {
+        if self.lock_window {
+            return;
+        }
+        let count = self.locker.monitors().len() as isize;
+        if count == 0 {
+            return;
+        }
+        let current = self.locker.current_monitor().unwrap_or(0) as isize;
+        let idx = (current + step).rem_euclid(count) as usize;
+        if self.locker.lock_to(idx) {
+            self.locker.warp_to_center();
+            status!("Changed lock to monitor {}", idx + 1);
+            self.lock_event();
+            self.toast(&format!("Switched to {}", self.monitor_description()));
+            self.config.monitor = Some(idx + 1);
+            save_config(&self.config);
+        }
+    }
+
+    /// Moves the lock to whichever of the two favorite monitors it isn't on,
+    /// or to the first one when it's on neither.
+    fn swap_favorite(&mut self) {
+        let Some([first, second]) = self.config.favorite_monitors else { return };
+        if self.lock_window {
+            return;
+        }
+        let count = self.locker.monitors().len();
+        if [first, second].iter().any(|&n| n == 0 || n > count) {
+            notice!("favorite_monitors = [{}, {}] names a monitor that isn't there (found {})", first, second, count);
+            return;
+        }
+        let target = if self.locker.current_monitor() == Some(first - 1) { second } else { first };
+        if self.locker.lock_to(target - 1) {
+            self.locker.warp_to_center();
+            status!("Favorite hotkey: Changed lock to monitor {}", target);
+            self.lock_event();
+            self.toast(&format!("Switched to {}", self.monitor_description()));
+            self.config.monitor = Some(target);
+            save_config(&self.config);
+        }
+    }
+
+    fn check_paused_app(&mut self) {
+        let hwnd = unsafe { GetForegroundWindow() };
+        if hwnd == self.checked_window {
+            return;
+        }
+        self.checked_window = hwnd;
+        let matched = !hwnd.is_null() && window::matches_any(hwnd, &self.pause_for);
+        if matched && !self.paused_for_app && self.locker.is_enabled() {
+            self.locker.set_enabled(false);
+            self.paused_for_app = true;
+            status!("Lock paused while {} has focus",
+                window::process_name(hwnd).unwrap_or_else(|| "a listed window".to_string()));
+            event!("disabled");
+        } else if !matched && self.paused_for_app {
+            self.locker.set_enabled(true);
+            self.paused_for_app = false;
+            status!("Lock resumed");
+            event!("enabled");
+        }
+    }
+
+    /// Switches to the profile after the active one, in name order.
+    fn cycle_profile(&mut self) {
+        let names: Vec<&String> = self.config.profiles.keys().collect();
+        if names.is_empty() {
+            return;
+        }
+        let next = match self.active_profile.as_ref().and_then(|a| names.iter().position(|n| *n == a)) {
+            Some(i) => (i + 1) % names.len(),
+            None => 0,
+        };
+        let name = names[next].clone();
+        self.apply_profile(&name);
+    }
+
+    /// Reconfigures the locker from a profile and re-locks with it.
+    fn apply_profile(&mut self, name: &str) {
+        let Some(profile) = self.config.profiles.get(name).cloned() else { return };
+        if let Err(e) = profile.apply_settings(&mut self.locker) {
+            notice!("Profile \"{}\": {}", name, e);
+            return;
+        }
+        self.active_profile = Some(name.to_string());
+        status!("Switched to profile \"{}\"", name);
+        if self.lock_window {
+            return;
+        }
+        let current = self.locker.current_monitors();
+        let indices = match &profile.monitor {
+            Some(selector) => match resolve_selector(selector, self.locker.monitors(), get_current_monitor_index(self.locker.monitors())) {
+                Ok(indices) => indices.unwrap_or(current),
+                Err(e) => {
+                    notice!("Profile \"{}\": {}; keeping the current monitor", name, e);
+                    current
+                }
+            },
+            None => current,
+        };
+        // Re-locking picks up the new work area, insets and lock mode
+        if !indices.is_empty() && self.locker.lock_to_group(&indices) {
+            self.lock_event();
+            self.toast(&format!("Profile \"{}\": locked to {}", name, self.monitor_description()));
+        }
+    }
+
+    /// Re-reads the config file and applies the lock settings and favorites
+    /// from it, moving the lock if the file names a different monitor. A file
+    /// that doesn't parse leaves everything as it was.
+    fn reload_config(&mut self) -> Result<(), String> {
+        let config = match Config::reload() {
+            Ok(config) => config,
+            Err(e) => {
+                notice!("Config not reloaded, keeping the current settings: {}", e);
+                return Err(e);
+            }
+        };
+        self.locker.set_work_area(config.work_area);
+        self.locker.set_soft_lock(config.soft_lock);
+        self.locker.set_smooth_warp(config.smooth_warp);
+        self.locker.set_lock_axis(config.lock_axis);
+        self.locker.set_shape(config.shape);
+        self.locker.set_edge_margin(config.edge_margin);
+        self.locker.set_relock_inset(config.relock_inset);
+        self.locker.set_insets(config.insets);
+        self.locker.set_monitor_overrides(config.overrides.clone().into_iter().collect());
+        let old = std::mem::replace(&mut self.config, config);
+        // The active profile's settings came from the old file
+        self.active_profile = None;
+        status!("Config reloaded");
+        if self.config.monitor_order != old.monitor_order {
+            notice!("Config reload: monitor_order takes effect after a restart");
+        }
+        if self.lock_window {
+            return Ok(());
+        }
+
+        let monitors = self.locker.monitors();
+        let mut indices = self.locker.current_monitors();
+        if self.config.monitor_selector != old.monitor_selector {
+            if let Some(selector) = &self.config.monitor_selector {
+                match resolve_selector(selector, monitors, get_current_monitor_index(monitors)) {
+                    Ok(Some(found)) => indices = found,
+                    Ok(None) => {}
+                    Err(e) => notice!("Config reload: {}; keeping the current monitor", e),
+                }
+            }
+        } else if self.config.monitor != old.monitor {
+            match self.config.monitor {
+                Some(n) if n > 0 && n <= monitors.len() => indices = vec![n - 1],
+                Some(n) => notice!("Config reload: monitor {} does not exist; keeping the current monitor", n),
+                None => {}
+            }
+        }
+        // Re-locking picks up the new work area, insets and lock mode
+        if !indices.is_empty() && self.locker.lock_to_group(&indices) {
+            status!("Config reload: locked to monitor {}", self.locked_monitors());
+            self.lock_event();
+        }
+        Ok(())
+    }
+
+    fn toggle_enabled(&mut self) {
+        // The hotkey overrides a pause for a listed app
+        self.paused_for_app = false;
+        let enabled = !self.locker.is_enabled();
+        self.locker.set_enabled(enabled);
+        status!("Lock {}", if enabled { "enabled" } else { "disabled" });
+        event!("{}", if enabled { "enabled" } else { "disabled" });
+    }
+
+    fn show_tray_menu(&mut self) {
+        let Some(tray) = &self.tray else { return };
+        let current = self.locker.current_monitors();
+        let mut items = Vec::new();
+        if !self.lock_window {
+            for (i, m) in self.locker.monitors().iter().enumerate() {
+                let rc = m.rect();
+                let mut label = format!("Monitor {}", i + 1);
+                if let Some(name) = m.friendly_name() {
+                    label += &format!(" [{}]", name);
+                }
+                label += &format!(" {}x{}", rc.right - rc.left, rc.bottom - rc.top);
+                label += &orientation_marker(m);
+                if m.is_primary() {
+                    label += " (primary)";
+                }
+                items.push(MenuItem::Entry { id: TRAY_MONITOR_BASE + i as u32, label, checked: current.contains(&i) });
+            }
+            items.push(MenuItem::Separator);
+        }
+        items.push(MenuItem::Entry { id: TRAY_TOGGLE, label: "Lock enabled".to_string(), checked: self.locker.is_enabled() });
+        items.push(MenuItem::Entry { id: TRAY_QUIT, label: "Quit".to_string(), checked: false });
+
+        match tray.show_menu(&items) {
+            Some(TRAY_TOGGLE) => self.toggle_enabled(),
+            // The loop exits on its next tick, through the same path as Ctrl+C
+            Some(TRAY_QUIT) => SHUTDOWN.store(true, Ordering::SeqCst),
+            Some(id) if id >= TRAY_MONITOR_BASE => {
+                self.switch_to((id - TRAY_MONITOR_BASE) as usize, "Tray menu", self.center_on_switch)
+            }
+            _ => {}
+        }
+        self.update_tray();
+    }
+
+    fn handle_pipe_requests(&mut self) {
+        let Some(requests) = &self.pipe_requests else { return };
+        let pending: Vec<pipe::Request> = requests.try_iter().collect();
+        for request in pending {
+            let reply = match request.command {
+                Ok(command) => self.run_pipe_command(command),
+                Err(e) => format!("ERR {}", e),
+            };
+            // The client may have hung up already
+            let _ = request.reply.send(reply);
+        }
+        self.update_tray();
+    }
+
+    fn run_pipe_command(&mut self, command: pipe::Command) -> String {
+        match command {
+            pipe::Command::Lock(n) => {
+                if self.lock_window {
+                    return "ERR locked to the foreground window".to_string();
+                }
+                if n > self.locker.monitors().len() {
+                    return format!("ERR monitor {} does not exist", n);
+                }
+                self.locker.set_enabled(true);
+                self.paused_for_app = false;
+                self.switch_to(n - 1, "Pipe command", self.center_on_switch);
+            }
+            pipe::Command::Release => {
+                if self.locker.is_enabled() {
+                    self.toggle_enabled();
+                }
+            }
+            pipe::Command::Toggle => self.toggle_enabled(),
+            pipe::Command::Reload => {
+                if let Err(e) = self.reload_config() {
+                    return format!("ERR {}", e);
+                }
+            }
+            pipe::Command::Status => {}
+        }
+        format!("OK {}", self.status_line())
+    }
+
+    /// The locked monitor numbers like `2` or `1,2`, `window` when following
+    /// the foreground window, or `none`.
+    fn locked_monitors(&self) -> String {
+        if self.lock_window {
+            return "window".to_string();
+        }
+        match self.locker.current_monitors().as_slice() {
+            [] if self.locker.current_rect().is_some() => "rect".to_string(),
+            [] => "none".to_string(),
+            indices => indices.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(","),
+        }
+    }
+
+    /// The lock state as `key=value` pairs, for the pipe and the status hotkey.
+    fn status_line(&self) -> String {
+        let rect = match self.locker.current_rect() {
+            Some(rc) => format!("{},{},{},{}", rc.left, rc.top, rc.right, rc.bottom),
+            None => "none".to_string(),
+        };
+        let mode = match (self.locker.soft_lock(), self.locker.lock_axis()) {
+            (true, _) => "soft",
+            (false, LockAxis::Both) => "clip",
+            (false, LockAxis::X) => "x",
+            (false, LockAxis::Y) => "y",
+        };
+        format!("monitor={} enabled={} clipped={} rect={} mode={} armed={}", self.locked_monitors(),
+            self.locker.is_enabled(), self.locker.is_clipped(), rect, mode, self.locker.is_release_armed())
+    }
+
+    fn lock_event(&self) {
+        lock_event(&self.locker, &self.locked_monitors());
+    }
+
+    fn toast(&self, body: &str) {
+        if let Some(tray) = self.tray.as_ref().filter(|_| self.toast) {
+            tray.notify("LockMouseToMonitor", body);
+        }
+    }
+
+    /// The locked monitor for messages, like `monitor 2 [DELL U2415]`.
+    fn monitor_description(&self) -> String {
+        match self.locker.current_monitors().as_slice() {
+            [] => "no monitor".to_string(),
+            [i] => match self.locker.monitors()[*i].friendly_name() {
+                Some(name) => format!("monitor {} [{}]", i + 1, name),
+                None => format!("monitor {}", i + 1),
+            },
+            group => format!("monitors {}",
+                group.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(", ")),
+        }
+    }
+
+    fn tray_tooltip(&self) -> String {
+        let state = if !self.locker.is_enabled() {
+            "disabled".to_string()
+        } else if self.lock_window {
+            "locked to the foreground window".to_string()
+        } else {
+            match self.locker.current_monitors().as_slice() {
+                [] => "not locked".to_string(),
+                _ => format!("locked to {}", self.monitor_description()),
+            }
+        };
+        match self.reapplies.1 {
+            0 => format!("LockMouseToMonitor: {}", state),
+            1 => format!("LockMouseToMonitor: {} \u{2014} 1 reapply", state),
+            n => format!("LockMouseToMonitor: {} \u{2014} {} reapplies", state, n),
+        }
+    }
+
+    /// Starts the tooltip's count over once the lock is on other monitors.
+    fn reset_reapplies_on_switch(&mut self) {
+        let monitors = self.locker.current_monitors();
+        if self.reapplies.0 != monitors {
+            self.reapplies = (monitors, 0);
+        }
+    }
+
+    fn update_tray(&mut self) {
+        self.reset_reapplies_on_switch();
+        let tooltip = self.tray_tooltip();
+        if let Some(tray) = &mut self.tray {
+            tray.set_tooltip(&tooltip);
+        }
+    }
+
+    /// Logs session events and puts the clip back once the user is back at
+    /// the desktop: Windows drops it on Win+L and across RDP reconnects, and
+    /// the copy in `GetClipCursor` can't be trusted to show that.
+    fn on_session_change(&mut self, event: WPARAM) {
+        let name = match event {
+            WTS_SESSION_LOCK => "locked",
+            WTS_SESSION_UNLOCK => "unlocked",
+            WTS_CONSOLE_CONNECT => "console connected",
+            WTS_CONSOLE_DISCONNECT => "console disconnected",
+            WTS_REMOTE_CONNECT => "remote session connected",
+            WTS_REMOTE_DISCONNECT => "remote session disconnected",
+            _ => return,
+        };
+        status!("Session {}", name);
+        if !matches!(event, WTS_SESSION_UNLOCK | WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT) {
+            return;
+        }
+        if matches!(event, WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT) {
+            // The new connection may come with a different set of displays
+            self.refresh_monitors();
+        }
+        if self.locker.is_clipped() && self.locker.relock() {
+            status!("Session {}: clip re-applied", name);
+        }
+    }
+
+    /// Notes switches to and from the secure desktop; true while it's up.
+    /// Coming back re-clips to the lock, which Windows dropped meanwhile.
+    fn check_secure_desktop(&mut self) -> bool {
+        let secure = !input_desktop_accessible();
+        if secure == self.on_secure_desktop {
+            return secure;
+        }
+        self.on_secure_desktop = secure;
+        if secure {
+            status!("Switched to the secure desktop; the clip waits until it's gone");
+            event!("secure-desktop");
+        } else {
+            status!("Back on the interactive desktop");
+            event!("interactive-desktop");
+            if self.locker.is_clipped() && self.locker.relock() {
+                status!("Clip re-applied");
+            }
+        }
+        secure
+    }
+
+    fn refresh_monitors(&mut self) {
+        let had_all = self.locker.current_monitors().len() == self.locker.monitors().len();
+        let refresh = self.locker.refresh_monitors();
+        self.report_refresh(refresh);
+        let count = self.locker.monitors().len();
+        if self.lock_all && had_all && self.locker.current_monitors().len() != count {
+            // Take in a monitor that was just connected
+            let all: Vec<usize> = (0..count).collect();
+            if self.locker.lock_to_group(&all) {
+                status!("Display change: locked to all {} monitors", count);
+                self.lock_event();
+            }
+        }
+    }
+
+    fn report_refresh(&mut self, refresh: Refresh) {
+        match refresh {
+            Refresh::Unchanged => {}
+            Refresh::Moved(rc) => status!("Display change: lock updated to left={} top={} right={} bottom={}",
+                rc.left, rc.top, rc.right, rc.bottom),
+            Refresh::FellBackToPrimary(rc) => status!(
+                "Display change: locked monitor is gone, falling back to the primary monitor: left={} top={} right={} bottom={}",
+                rc.left, rc.top, rc.right, rc.bottom),
+            Refresh::NoMonitors => {
+                status!("Display change: no monitor left to lock to, releasing clip");
+                event!("released");
+                return;
+            }
+        }
+        if !matches!(refresh, Refresh::Unchanged) {
+            self.lock_event();
+        }
     }
-    // Sort monitors by their left coordinate for consistent ordering
-    monitors.sort_by_key(|m: &MonitorInfo| m.rect.left);
-    monitors
 }
 
-fn get_current_monitor_index(monitors: &[MonitorInfo]) -> Option<usize> {
+/// Takes the single-instance mutex. Returns `None` if another instance holds it.
+fn acquire_instance_mutex() -> Option<HANDLE> {
+    let name: Vec<u16> = INSTANCE_MUTEX_NAME.encode_utf16().chain(Some(0)).collect();
     unsafe {
-        let mut pt: POINT = std::mem::zeroed();
-        if GetCursorPos(&mut pt) == 0 {
+        let handle = CreateMutexW(ptr::null_mut(), FALSE, name.as_ptr());
+        if !handle.is_null() && GetLastError() == ERROR_ALREADY_EXISTS {
+            CloseHandle(handle);
             return None;
         }
-        // Find which monitor contains the cursor
-        monitors.iter().position(|m| point_in_rect(&pt, &m.rect))
+        // If the mutex couldn't be created at all, run anyway rather than refuse
+        Some(handle)
+    }
+}
+
+/// The cursor position, if `GetCursorPos` gets one.
+fn cursor_pos() -> Option<POINT> {
+    let mut pt: POINT = unsafe { std::mem::zeroed() };
+    (unsafe { GetCursorPos(&mut pt) } != 0).then_some(pt)
+}
+
+/// `--warp-in`: moves the cursor to the middle of the lock if it was outside
+/// it at `before`, since `ClipCursor` only pushes it to the nearest edge.
+fn warp_in(locker: &MonitorLocker, before: Option<POINT>) {
+    if let Some(pt) = before.filter(|pt| !locker.contains(pt.x, pt.y)) {
+        locker.warp_to_center();
+        status!("The cursor was outside the lock at {},{}; moved it to the middle", pt.x, pt.y);
+    }
+}
+
+/// The monitor the cursor is on, or the primary one if the cursor is (briefly)
+/// off every display.
+fn current_or_primary(current_monitor_idx: Option<usize>, monitors: &[MonitorInfo]) -> Option<usize> {
+    current_monitor_idx.or_else(|| {
+        let primary = get_primary_monitor_index(monitors);
+        if primary.is_some() {
+            println!("The cursor isn't on any monitor; using the primary monitor instead");
+        }
+        primary
+    })
+}
+
+/// Index of the monitor the foreground window is mostly on.
+fn foreground_monitor(monitors: &[MonitorInfo]) -> Option<usize> {
+    let hwnd = unsafe { GetForegroundWindow() };
+    if hwnd.is_null() {
+        return None;
     }
+    let hmon = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONULL) };
+    monitors.iter().position(|m| m.handle() == hmon)
 }
 
-fn get_monitor_rect_for_point(x: i32, y: i32) -> Option<RECT> {
-    let pt = POINT { x, y };
-    let hmon = unsafe { MonitorFromPoint(pt, MONITOR_DEFAULTTONEAREST) };
-    if hmon.is_null() {
+/// The monitor `hwnd` covers entirely, if any. The desktop also covers its
+/// monitor, so it doesn't count.
+fn fullscreen_monitor(hwnd: HWND, monitors: &[MonitorInfo]) -> Option<usize> {
+    if hwnd.is_null() || hwnd == unsafe { GetShellWindow() } || is_desktop_window(hwnd) {
         return None;
     }
-    let mut mi: MONITORINFO = unsafe { std::mem::zeroed() };
-    mi.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
-    let ok = unsafe { GetMonitorInfoW(hmon, &mut mi as *mut MONITORINFO) };
-    if ok == 0 {
+    let mut rc: RECT = unsafe { std::mem::zeroed() };
+    if unsafe { IsIconic(hwnd) } != 0 || unsafe { GetWindowRect(hwnd, &mut rc) } == 0 {
         return None;
     }
-    Some(mi.rcMonitor)
+    let hmon = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONULL) };
+    let idx = monitors.iter().position(|m| m.handle() == hmon)?;
+    let mon = monitors[idx].rect();
+    let close = |a: i32, b: i32| (a - b).abs() <= FULLSCREEN_TOLERANCE;
+    let covers = close(rc.left, mon.left) && close(rc.top, mon.top)
+        && close(rc.right, mon.right) && close(rc.bottom, mon.bottom);
+    covers.then_some(idx)
 }
 
-fn point_in_rect(pt: &POINT, rc: &RECT) -> bool {
-    pt.x >= rc.left && pt.x < rc.right && pt.y >= rc.top && pt.y < rc.bottom
+/// `--monitor fullscreen`: the monitor of the topmost visible window that
+/// covers one. Click-through windows are skipped, as those are overlays
+/// (FPS counters, screen recorders) rather than what's being played.
+fn fullscreen_window_monitor(monitors: &[MonitorInfo]) -> Option<usize> {
+    struct Search<'a> {
+        monitors: &'a [MonitorInfo],
+        found: Option<usize>,
+    }
+    unsafe extern "system" fn visit(hwnd: HWND, data: LPARAM) -> BOOL {
+        let search = &mut *(data as *mut Search);
+        if IsWindowVisible(hwnd) == 0 || GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TRANSPARENT != 0 {
+            return TRUE;
+        }
+        search.found = fullscreen_monitor(hwnd, search.monitors);
+        // EnumWindows goes from the top of the Z order down, so stop at the first
+        if search.found.is_some() { FALSE } else { TRUE }
+    }
+    let mut search = Search { monitors, found: None };
+    unsafe { EnumWindows(Some(visit), &mut search as *mut Search as LPARAM) };
+    search.found
 }
 
-fn at_rect_edge(pt: &POINT, rc: &RECT) -> bool {
-    // consider 1-pixel margin as "edge"
-    pt.x <= rc.left + 1 || pt.x >= rc.right - 1 || pt.y <= rc.top + 1 || pt.y >= rc.bottom - 1
+fn numbered_indices(numbers: &[usize], monitors: &[MonitorInfo]) -> Result<Vec<usize>, String> {
+    if let Some(n) = numbers.iter().find(|&&n| n > monitors.len()) {
+        return Err(format!("Monitor {} does not exist (found {} monitors)", n, monitors.len()));
+    }
+    Ok(numbers.iter().map(|n| n - 1).collect())
 }
 
-fn main() {
-    println!("lockmousetomonitor - locks cursor to selected monitor");
-    println!("Controls:");
-    println!("- Press Ctrl to temporarily release lock when cursor reaches monitor edge");
-    println!("- Press F11 to change which monitor is locked (while cursor is on the desired monitor)");
-    println!("\nAvailable monitors:");
+/// Turns a selector into monitor indices. Errors if a number is out of range
+/// or a resolution or position matches no monitor, or more than one.
+fn resolve_selector(selector: &MonitorSelector, monitors: &[MonitorInfo], current_monitor_idx: Option<usize>) -> Result<Option<Vec<usize>>, String> {
+    let matches: Vec<usize> = match selector {
+        MonitorSelector::Number(n) => return numbered_indices(std::slice::from_ref(n), monitors).map(Some),
+        MonitorSelector::Group(numbers) => return numbered_indices(numbers, monitors).map(Some),
+        MonitorSelector::All => return Ok(Some((0..monitors.len()).collect())),
+        MonitorSelector::Exclude(n) => {
+            let excluded = numbered_indices(std::slice::from_ref(n), monitors)?[0];
+            if monitors.len() < 2 {
+                return Err(format!("Excluding monitor {} leaves no monitor to lock to", n));
+            }
+            return Ok(Some((0..monitors.len()).filter(|&i| i != excluded).collect()));
+        }
+        MonitorSelector::Current => return Ok(current_or_primary(current_monitor_idx, monitors).map(|i| vec![i])),
+        MonitorSelector::Primary => return Ok(get_primary_monitor_index(monitors).map(|i| vec![i])),
+        MonitorSelector::Foreground => {
+            let idx = foreground_monitor(monitors).or_else(|| {
+                println!("No foreground window; using the cursor's monitor instead");
+                current_or_primary(current_monitor_idx, monitors)
+            });
+            return Ok(idx.map(|i| vec![i]));
+        }
+        MonitorSelector::Fullscreen => {
+            let idx = fullscreen_window_monitor(monitors).or_else(|| {
+                println!("No fullscreen window; using the cursor's monitor instead");
+                current_or_primary(current_monitor_idx, monitors)
+            });
+            return Ok(idx.map(|i| vec![i]));
+        }
+        MonitorSelector::Resolution { width, height } => monitors.iter().enumerate()
+            .filter(|(_, m)| {
+                let rc = m.rect();
+                rc.right - rc.left == *width && rc.bottom - rc.top == *height
+            })
+            .map(|(i, _)| i)
+            .collect(),
+        MonitorSelector::Position { x, y } => monitors.iter().enumerate()
+            .filter(|(_, m)| m.rect().left == *x && m.rect().top == *y)
+            .map(|(i, _)| i)
+            .collect(),
+        MonitorSelector::Device(device) => monitors.iter().enumerate()
+            .filter(|(_, m)| m.device_name().eq_ignore_ascii_case(device)
+                || m.device_path().is_some_and(|path| path.eq_ignore_ascii_case(device)))
+            .map(|(i, _)| i)
+            .collect(),
+    };
+    match matches.as_slice() {
+        [] => Err(format!("No monitor matches \"{}\"", selector)),
+        [idx] => Ok(Some(vec![*idx])),
+        _ => {
+            let numbers: Vec<String> = matches.iter().map(|i| (i + 1).to_string()).collect();
+            Err(format!("Monitors {} all match \"{}\"; use a more specific selector such as at:x,y",
+                numbers.join(", "), selector))
+        }
+    }
+}
 
-    let monitors = get_all_monitors();
-    if monitors.is_empty() {
-        println!("No monitors found!");
-        return;
+/// Picks the monitor to lock to at startup: from `--monitor`, the saved
+/// config, or by asking on stdin. Returns the indices to lock to; more than
+/// one for a `--monitor 1,2` group.
+fn choose_monitor(args: &Args, selector: Option<&MonitorSelector>, config: &mut Config, monitors: &[MonitorInfo], current_monitor_idx: Option<usize>) -> Option<Vec<usize>> {
+    if let Some(selector) = selector {
+        match resolve_selector(selector, monitors, current_monitor_idx) {
+            Ok(indices) => indices,
+            Err(e) => fail(LockError::InvalidSelection(e)),
+        }
+    } else if args.daemon {
+        // Never prompt; the saved monitor counts even if the prompt wasn't skipped
+        let saved = config.monitor.filter(|&n| n > 0 && n <= monitors.len()).map(|n| n - 1);
+        saved.or_else(|| get_primary_monitor_index(monitors)).map(|i| vec![i])
+    } else if args.quiet {
+        current_or_primary(current_monitor_idx, monitors).map(|i| vec![i])
+    } else if let Some(idx) = config.saved_monitor_index(monitors.len()) {
+        println!("\nUsing saved monitor {} from config", idx + 1);
+        Some(vec![idx])
+    } else {
+        println!("\nEnter monitor number to lock to (1-{}), or press Enter for current monitor:", monitors.len());
+
+        let mut attempts = 0;
+        let selected_idx = loop {
+            let mut input = String::new();
+            match std::io::stdin().read_line(&mut input) {
+                // No stdin to read from, e.g. started by Task Scheduler. Not
+                // a real choice, so it isn't saved
+                Ok(0) => {
+                    println!("No input available; using the current monitor");
+                    return current_or_primary(current_monitor_idx, monitors).map(|i| vec![i]);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    println!("Couldn't read input ({}); using the current monitor", e);
+                    return current_or_primary(current_monitor_idx, monitors).map(|i| vec![i]);
+                }
+            }
+            let input = input.trim();
+
+            if input.is_empty() {
+                // Use current monitor if we found one
+                break current_or_primary(current_monitor_idx, monitors);
+            }
+            // Parse user selection
+            match input.parse::<usize>() {
+                Ok(n) if n > 0 && n <= monitors.len() => break Some(n - 1),
+                _ => {
+                    attempts += 1;
+                    if attempts == MAX_PROMPT_ATTEMPTS {
+                        println!("Too many invalid answers; using the current monitor");
+                        return current_or_primary(current_monitor_idx, monitors).map(|i| vec![i]);
+                    }
+                    println!("Invalid monitor number! Enter 1-{}, or press Enter for current monitor:", monitors.len());
+                }
+            }
+        };
+
+        // Remember the choice so the next launch can skip the prompt
+        if let Some(idx) = selected_idx.filter(|_| !args.dry_run) {
+            config.monitor = Some(idx + 1);
+            config.skip_prompt = true;
+            save_config(config);
+        }
+        selected_idx.map(|i| vec![i])
     }
+}
 
-    // Find which monitor currently contains the cursor
-    let current_monitor_idx = get_current_monitor_index(&monitors);
-    
+/// `EVENT locked monitor=2 rect=0,0,1920,1080`
+fn lock_event(locker: &MonitorLocker, monitor: &str) {
+    if let Some(rc) = locker.current_rect() {
+        event!("locked monitor={} rect={},{},{},{}", monitor, rc.left, rc.top, rc.right, rc.bottom);
+    }
+}
+
+/// " (portrait)" and/or the rotation, so tall and wide displays with the
+/// same resolution can be told apart; empty for an unrotated landscape one.
+fn orientation_marker(monitor: &MonitorInfo) -> String {
+    match (monitor.is_portrait(), monitor.orientation()) {
+        (false, 0) => String::new(),
+        (true, 0) => " (portrait)".to_string(),
+        (false, degrees) => format!(" (rotated {} degrees)", degrees),
+        (true, degrees) => format!(" (portrait, rotated {} degrees)", degrees),
+    }
+}
+
+/// Prints the numbered monitor list shown before the prompt.
+fn print_monitors(monitors: &[MonitorInfo], current_monitor_idx: Option<usize>) {
+    println!("\nAvailable monitors:");
     for (i, monitor) in monitors.iter().enumerate() {
+        let primary_marker = if monitor.is_primary() { " (primary)" } else { "" };
         let current_marker = if Some(i) == current_monitor_idx { " (current)" } else { "" };
-        println!("{}. Monitor {}: {}x{} at ({}, {}) to ({}, {}){}", 
+        let mirror_marker = match mirror_of(monitors, i) {
+            Some(j) => format!(" (mirrored, same as {})", j + 1),
+            None => String::new(),
+        };
+        let rect = monitor.rect();
+        let name = match monitor.friendly_name() {
+            Some(friendly) => format!("{} ({})", friendly, monitor.device_name()),
+            None => monitor.device_name().to_string(),
+        };
+        println!("{}. Monitor {} [{}]: {}x{} at ({}, {}) to ({}, {}){}{}{}{}", 
             i + 1,
             i + 1,
-            monitor.rect.right - monitor.rect.left,
-            monitor.rect.bottom - monitor.rect.top,
-            monitor.rect.left, monitor.rect.top,
-            monitor.rect.right, monitor.rect.bottom,
-            current_marker
+            name,
+            rect.right - rect.left,
+            rect.bottom - rect.top,
+            rect.left, rect.top,
+            rect.right, rect.bottom,
+            orientation_marker(monitor),
+            primary_marker,
+            current_marker,
+            mirror_marker
         );
     }
+}
 
-    println!("\nEnter monitor number to lock to (1-{}), or press Enter for current monitor:", monitors.len());
-    
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-    let input = input.trim();
-
-    let initial_rect = if input.is_empty() {
-        // Use current monitor if we found one
-        current_monitor_idx.map(|idx| monitors[idx].rect)
+/// `--dry-run`: the settings that decide what the cursor is held to, after
+/// the rect itself has been printed.
+fn print_dry_run(locker: &MonitorLocker) {
+    let mode = if locker.soft_lock() {
+        "soft lock (the cursor is moved back on every check)".to_string()
     } else {
-        // Parse user selection
-        match input.parse::<usize>() {
-            Ok(n) if n > 0 && n <= monitors.len() => {
-                Some(monitors[n - 1].rect)
-            }
-            _ => {
-                println!("Invalid monitor number!");
-                return;
-            }
+        match locker.lock_axis() {
+            LockAxis::Both => "ClipCursor".to_string(),
+            LockAxis::X => "x axis only (the cursor is moved back on every check)".to_string(),
+            LockAxis::Y => "y axis only (the cursor is moved back on every check)".to_string(),
         }
     };
+    let insets = locker.insets();
+    println!("Mode: {}", mode);
+    println!("Work area: {}", if locker.work_area() { "yes" } else { "no" });
+    println!("Insets: left={} top={} right={} bottom={}", insets.left, insets.top, insets.right, insets.bottom);
+    println!("Edge margin: {}", locker.edge_margin());
+    println!("Dry run: nothing was clipped");
+}
 
-    let mut prev_ctrl = false;
-    let mut release_on_exit = false;
-    let mut clipped = false;
-    let mut current_rect: Option<RECT> = None;
-
-    // Initial lock using selected monitor
-    if let Some(rc) = initial_rect {
-        unsafe {
-            let rc_ptr: *const RECT = &rc as *const RECT;
-            if ClipCursor(rc_ptr) != 0 {
-                clipped = true;
-                current_rect = Some(rc);
-                println!("Locked to monitor rect: left={} top={} right={} bottom={}", 
-                    rc.left, rc.top, rc.right, rc.bottom);
-            }
+/// One entry of the `--list` output.
+#[derive(Serialize)]
+struct MonitorListing<'a> {
+    /// The number to pass to `--monitor`.
+    index: usize,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+    width: i32,
+    height: i32,
+    device_name: &'a str,
+    friendly_name: Option<&'a str>,
+    /// Stable across reboots, for `--monitor device:...`.
+    device_path: Option<&'a str>,
+    /// Clockwise rotation in degrees.
+    orientation: u32,
+    portrait: bool,
+    primary: bool,
+    /// The number of the monitor this one is a mirror clone of.
+    mirror_of: Option<usize>,
+}
+
+/// `--list`: prints the monitors as a JSON array for other tools to parse.
+fn print_monitor_list(monitors: &[MonitorInfo]) {
+    let listing: Vec<MonitorListing> = monitors.iter().enumerate().map(|(i, m)| {
+        let rect = m.rect();
+        MonitorListing {
+            index: i + 1,
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+            width: rect.right - rect.left,
+            height: rect.bottom - rect.top,
+            device_name: m.device_name(),
+            friendly_name: m.friendly_name(),
+            device_path: m.device_path(),
+            orientation: m.orientation(),
+            portrait: m.is_portrait(),
+            primary: m.is_primary(),
+            mirror_of: mirror_of(monitors, i).map(|j| j + 1),
         }
-    } else {
-        println!("Failed to get monitor rectangle!");
+    }).collect();
+    println!("{}", serde_json::to_string_pretty(&listing).expect("monitor listing serializes"));
+}
+
+/// The process exit code for `error`, for scripts. A bad command line or
+/// config exits with 1 as it always has.
+/// Exit code when another instance already holds the instance mutex. The
+/// service goes by it to leave a session alone instead of restarting its copy.
+const EXIT_ALREADY_RUNNING: i32 = 4;
+
+fn exit_code(error: &LockError) -> i32 {
+    match error {
+        LockError::InvalidSelection(_) | LockError::InvalidSetting(_) => 1,
+        LockError::NoMonitors => 2,
+        LockError::ClipFailed(_) => 3,
+    }
+}
+
+/// Reports `error` the way startup always has and exits with its code.
+fn fail(error: LockError) -> ! {
+    match error {
+        // Mistakes in the options go to stderr, even with --quiet
+        LockError::InvalidSelection(_) | LockError::InvalidSetting(_) => eprintln!("{}", error),
+        LockError::NoMonitors | LockError::ClipFailed(_) => notice!("{}", error),
+    }
+    std::process::exit(exit_code(&error))
+}
+
+fn save_config(config: &Config) {
+    if let Err(e) = config.save() {
+        notice!("Failed to save config: {}", e);
+    }
+}
+
+fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.help {
+        print!("{}", USAGE);
         return;
     }
+    if args.version {
+        println!("lockmousetomonitor {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    // Before anything asks for monitor or cursor coordinates
+    lockmousetomonitor::enable_dpi_awareness();
 
-    loop {
-        thread::sleep(Duration::from_millis(16)); // ~60Hz check rate
+    // Read early, as the monitor order decides the numbers --list shows
+    let mut config = Config::load().unwrap_or_default();
+    let monitor_order = args.monitor_order.or(config.monitor_order).unwrap_or_default();
+    let mut all_monitors = get_all_monitors_by(monitor_order);
+    if args.no_duplicates {
+        remove_mirrors(&mut all_monitors);
+    }
+    if args.list {
+        print_monitor_list(&all_monitors);
+        return;
+    }
+    if args.identify {
+        if !identify::show_numbers(&all_monitors, IDENTIFY_DURATION) {
+            notice!("Couldn't show the monitor numbers");
+        }
+        return;
+    }
 
-        // poll cursor and keyboard state
-        let mut pt: POINT = unsafe { std::mem::zeroed() };
-        let got = unsafe { GetCursorPos(&mut pt) };
-        if got == 0 {
-            continue;
+    if args.events {
+        logging::enable_events();
+    }
+    if let Err(e) = logging::init(args.verbose) {
+        if !args.silent() {
+            println!("Couldn't open the log file: {}", e);
+        }
+    }
+    if args.silent() {
+        // Everything goes to the log from here on
+        logging::disable_console();
+    }
+    if args.service {
+        if let Err(code) = service::run(args.logon_screen) {
+            eprintln!("--service only works when started by the Service Control Manager (error {})", code);
+            std::process::exit(1);
         }
+        return;
+    }
 
-        let ctrl_pressed = unsafe { (GetAsyncKeyState(VK_CONTROL) as i16) < 0 };
-        let lalt_pressed = unsafe { (GetAsyncKeyState(VK_LMENU) as i16) < 0 };
-        let f11_pressed = unsafe { (GetAsyncKeyState(VK_F11) as i16) < 0 };
+    // A dry run never clips, so it can run next to a real instance
+    let Some(instance_mutex) = (if args.dry_run { Some(ptr::null_mut()) } else { acquire_instance_mutex() }) else {
+        notice!("lockmousetomonitor is already running; exiting");
+        std::process::exit(EXIT_ALREADY_RUNNING);
+    };
 
-        let release_key_pressed = ctrl_pressed || lalt_pressed;
+    if let Some(warning) = Config::env_path_warning() {
+        notice!("Warning: {}", warning);
+    }
+    if let Some(warning) = Config::parse_warning() {
+        notice!("Warning: {}", warning);
+    }
 
-        // Always reapply clipping if we're supposed to be clipped
-        // This ensures it stays active even after alt-tab
-        if clipped && !release_on_exit {
-            if let Some(rc) = &current_rect {
-                unsafe { ClipCursor(rc) };
-            }
+    let switch_hotkey = config.switch_hotkey.unwrap_or(DEFAULT_SWITCH_HOTKEY);
+    let toggle_hotkey = config.toggle_hotkey.unwrap_or(DEFAULT_TOGGLE_HOTKEY);
+    let next_hotkey = config.next_monitor_hotkey.unwrap_or(DEFAULT_NEXT_MONITOR_HOTKEY);
+    let prev_hotkey = config.prev_monitor_hotkey.unwrap_or(DEFAULT_PREV_MONITOR_HOTKEY);
+    let profile_hotkey = config.profile_hotkey.unwrap_or(DEFAULT_PROFILE_HOTKEY);
+    let favorite_hotkey = config.favorite_hotkey.unwrap_or(DEFAULT_FAVORITE_HOTKEY);
+    let jump_modifiers = config.jump_modifiers.unwrap_or(DEFAULT_JUMP_MODIFIERS);
+    let reload_hotkey = config.reload_hotkey.unwrap_or(DEFAULT_RELOAD_HOTKEY);
+    let timed_release_hotkey = config.timed_release_hotkey.unwrap_or(DEFAULT_TIMED_RELEASE_HOTKEY);
+    let pick_hotkey = config.pick_monitor_hotkey.unwrap_or(DEFAULT_PICK_MONITOR_HOTKEY);
+    let status_hotkey = config.status_hotkey.unwrap_or(DEFAULT_STATUS_HOTKEY);
+    let timed_release = args.timed_release.or(config.timed_release).filter(|&secs| secs > 0).map(Duration::from_secs);
+    if !args.silent() {
+        println!("lockmousetomonitor - locks cursor to selected monitor");
+        println!("Controls:");
+        println!("- Press Ctrl to temporarily release lock when cursor reaches monitor edge");
+        println!("- Press {} to change which monitor is locked (while cursor is on the desired monitor)", switch_hotkey);
+        println!("- Press {} to free the cursor and lock to the monitor you move it to", pick_hotkey);
+        println!("- Press {} / {} to move the lock to the next / previous monitor", next_hotkey, prev_hotkey);
+        println!("- Press {} to turn the lock off or back on", toggle_hotkey);
+        println!("- Press {} to reload the config file", reload_hotkey);
+        println!("- Press {} to print the current state", status_hotkey);
+        if !config.profiles.is_empty() {
+            println!("- Press {} to switch to the next profile", profile_hotkey);
+        }
+        if let Some([first, second]) = config.favorite_monitors {
+            println!("- Press {} to swap the lock between monitors {} and {}", favorite_hotkey, first, second);
+        }
+        if jump_modifiers.0 != 0 {
+            println!("- Press {}+Numpad1-9 to lock straight to that monitor", jump_modifiers);
+        }
+        if let Some(duration) = timed_release {
+            println!("- Press {} to free the cursor for {}s", timed_release_hotkey, duration.as_secs());
         }
+    }
 
-        if release_key_pressed && !prev_ctrl {
-            // Release key-down event
-            release_on_exit = true;
-            println!("Ctrl/Alt pressed: will release the clip the next time the cursor hits the monitor edge");
+    if let Some(delay) = args.delay.or(config.startup_delay).filter(|&secs| secs > 0) {
+        notice!("Waiting {}s for the displays to settle", delay);
+        thread::sleep(Duration::from_secs(delay));
+    }
+    let mut locker = MonitorLocker::with_order(monitor_order);
+    locker.set_skip_mirrors(args.no_duplicates);
+    *SAVED_CLIP.lock().unwrap_or_else(|e| e.into_inner()) = locker.saved_clip();
+    if let Some(rc) = locker.saved_clip() {
+        notice!("Warning: another program already has the cursor clipped; it's put back when the lock lets go, \
+            but the two may fight over the clip while both run");
+        status!("Clip found at startup: left={} top={} right={} bottom={}", rc.left, rc.top, rc.right, rc.bottom);
+    }
+    // Enumeration can come back empty in the middle of a display switch, e.g.
+    // when started at boot, so give the displays a moment before giving up
+    for attempt in 1..=MONITOR_RETRIES {
+        if !locker.monitors().is_empty() {
+            break;
         }
-        prev_ctrl = release_key_pressed;
+        notice!("No monitors found; retrying in {}s (attempt {} of {})", MONITOR_RETRY_DELAY.as_secs(), attempt, MONITOR_RETRIES);
+        thread::sleep(MONITOR_RETRY_DELAY);
+        locker.refresh_monitors();
+    }
+    let monitors = locker.monitors();
+    if monitors.is_empty() {
+        fail(LockError::NoMonitors);
+    }
 
-        // Handle monitor edge detection and release
-        if let Some(rc) = &current_rect {
-            if clipped && release_on_exit && at_rect_edge(&pt, rc) {
-                unsafe { ClipCursor(ptr::null()) };
-                clipped = false;
-                println!("Released clip – you can move to other monitors now");
-            } else if !clipped && point_in_rect(&pt, rc) {
-                // Re-lock when returning to monitor
-                unsafe { ClipCursor(rc) };
-                clipped = true;
-                release_on_exit = false;
-                println!("Cursor returned to monitor; re-locked");
+    // Find which monitor currently contains the cursor
+    let current_monitor_idx = get_current_monitor_index(monitors);
+    if !args.silent() {
+        print_monitors(monitors, current_monitor_idx);
+    }
+
+    let lock_window = args.lock_window || config.lock_window;
+    let center_on_switch = args.center_on_switch || config.center_on_switch;
+    let warp_in_enabled = args.warp_in || config.warp_in;
+    let follow_fullscreen = args.follow_fullscreen || config.follow_fullscreen;
+    let game_mode = args.game_mode || config.game_mode;
+    let double_tap = (args.double_tap_release || config.double_tap_release)
+        .then(|| Duration::from_millis(config.double_tap_window_ms.unwrap_or(DEFAULT_DOUBLE_TAP_MS)));
+    let relock_after = args.relock_after.or(config.relock_after).filter(|&secs| secs > 0).map(Duration::from_secs);
+    let pause_for = config.pause_for.clone();
+    let fast_poll = config.poll_interval_ms.unwrap_or(POLL_INTERVAL_MS).max(1);
+    // Never slower than asked for while active
+    let poll_intervals = (fast_poll, config.idle_poll_interval_ms.unwrap_or(IDLE_POLL_INTERVAL_MS).max(fast_poll));
+    let sound = args.sound || config.sound;
+    let config_pipe = config.pipe;
+    let release_keys = config.release_keys.as_ref()
+        .map(|keys| keys.iter().map(|key| key.0).collect())
+        .unwrap_or_else(|| DEFAULT_RELEASE_KEYS.to_vec());
+    let work_area = args.work_area || config.work_area;
+    locker.set_work_area(work_area);
+    locker.set_soft_lock(args.soft_lock || config.soft_lock);
+    locker.set_smooth_warp(args.smooth_warp || config.smooth_warp);
+    locker.set_edge_margin(args.edge_margin.unwrap_or(config.edge_margin));
+    locker.set_relock_inset(args.relock_inset.or(config.relock_inset));
+    locker.set_lock_axis(args.lock_axis.unwrap_or(config.lock_axis));
+    locker.set_shape(args.shape.unwrap_or(config.shape));
+    let insets = args.insets(config.insets);
+    if let Err(e) = insets.validate() {
+        fail(LockError::InvalidSetting(e));
+    }
+    locker.set_insets(insets);
+    for (name, o) in &config.overrides {
+        if let Err(e) = o.insets.unwrap_or_default().validate() {
+            fail(LockError::InvalidSetting(format!("Override for {}: {}", name, e)));
+        }
+    }
+    locker.set_monitor_overrides(config.overrides.clone().into_iter().collect());
+
+    let profile = args.profile.as_ref().map(|name| match config.profiles.get(name) {
+        Some(p) => (name.clone(), p.clone()),
+        None => fail(LockError::InvalidSetting(format!("No profile named \"{}\" in the config", name))),
+    });
+    if let Some((name, p)) = &profile {
+        if let Err(e) = p.apply_settings(&mut locker) {
+            fail(LockError::InvalidSetting(format!("Profile \"{}\": {}", name, e)));
+        }
+        notice!("Using profile \"{}\"", name);
+    }
+
+    if args.dry_run {
+        // Resolve and compute everything as usual, but never call ClipCursor
+        locker.set_enabled(false);
+    }
+    let mut lock_all = false;
+    if let Some([left, top, right, bottom]) = args.rect {
+        let rc = RECT { left, top, right, bottom };
+        let on_a_monitor = locker.monitors().iter().any(|m| {
+            let mr = m.rect();
+            rc.left < mr.right && rc.right > mr.left && rc.top < mr.bottom && rc.bottom > mr.top
+        });
+        if !on_a_monitor {
+            notice!("Warning: the rect lies outside every monitor");
+        }
+        let before = cursor_pos();
+        if let Err(e) = locker.try_lock_to_rect(rc) {
+            fail(e);
+        }
+        if warp_in_enabled && !args.dry_run {
+            warp_in(&locker, before);
+        }
+        notice!("{} to rect: left={} top={} right={} bottom={}",
+            if args.dry_run { "Would lock" } else { "Locked" }, left, top, right, bottom);
+        if !args.dry_run {
+            lock_event(&locker, "rect");
+        }
+    } else if lock_window {
+        // The first tick picks up the foreground window
+        notice!("\nLocking to the foreground window");
+    } else {
+        // Initial lock using selected monitor
+        let selector = args.monitor.clone()
+            .or_else(|| profile.as_ref().and_then(|(_, p)| p.monitor.clone()))
+            .or_else(|| config.monitor_selector.clone());
+        lock_all = selector == Some(MonitorSelector::All);
+        let Some(indices) = choose_monitor(&args, selector.as_ref(), &mut config, locker.monitors(), current_monitor_idx) else {
+            notice!("Failed to get monitor rectangle!");
+            return;
+        };
+        let before = cursor_pos();
+        if let Err(e) = locker.try_lock_to_group(&indices) {
+            fail(e);
+        }
+        if center_on_switch && !args.dry_run {
+            locker.warp_to_center();
+        } else if warp_in_enabled && !args.dry_run {
+            warp_in(&locker, before);
+        }
+        let rc = locker.current_rect().unwrap();
+        let target = match (indices.len(), work_area) {
+            (1, false) => "monitor",
+            (1, true) => "work area",
+            (_, false) => "monitor group",
+            (_, true) => "work area group",
+        };
+        notice!("{} to {} rect: left={} top={} right={} bottom={}",
+            if args.dry_run { "Would lock" } else { "Locked" }, target, rc.left, rc.top, rc.right, rc.bottom);
+        if !locker.region().is_empty() {
+            notice!("The monitors don't form a rectangle; the cursor is kept off the gaps by warping it back");
+        }
+        if !args.dry_run {
+            let numbers: Vec<String> = indices.iter().map(|i| (i + 1).to_string()).collect();
+            lock_event(&locker, &numbers.join(","));
+        }
+    }
+    if args.dry_run {
+        print_dry_run(&locker);
+        return;
+    }
+    let mut app = App {
+        locker,
+        lock_window,
+        followed_window: ptr::null_mut(),
+        center_on_switch,
+        warp_in: warp_in_enabled,
+        follow_fullscreen,
+        game_mode,
+        lock_all,
+        fullscreen_window: ptr::null_mut(),
+        pause_for,
+        checked_window: ptr::null_mut(),
+        paused_for_app: false,
+        release_keys,
+        release_key: KeyEdge::default(),
+        hold_release: args.hold_release || config.hold_release,
+        holding_release: false,
+        double_tap,
+        last_release_tap: None,
+        relock_after,
+        released_at: None,
+        idle_release: args.idle_release.or(config.idle_release).filter(|&secs| secs > 0).map(Duration::from_secs),
+        last_cursor: POINT { x: 0, y: 0 },
+        last_moved: Instant::now(),
+        idle_released: false,
+        once: args.once,
+        timed_release,
+        timed_release_until: None,
+        pick_deadline: None,
+        drag_widen: args.drag_widen || config.drag_widen,
+        drag_at_edge: None,
+        on_secure_desktop: false,
+        polled_hotkeys: Vec::new(),
+        rate_sample_start: Some(Instant::now()),
+        ticks: 0,
+        tray: None,
+        overlay: None,
+        key_hook: None,
+        active_profile: profile.map(|(name, _)| name),
+        toast: args.toast || config.toast,
+        lock_sound: sound && args.lock_sound.unwrap_or(config.lock_sound.unwrap_or(true)),
+        release_sound: sound && args.release_sound.unwrap_or(config.release_sound.unwrap_or(true)),
+        clip_steals: HashMap::new(),
+        reapplies: (Vec::new(), 0),
+        steal_burst: None,
+        poll_intervals,
+        poll_interval: poll_intervals.0,
+        cursor_failures: 0,
+        timer_id: 0,
+        last_active: Instant::now(),
+        was_clipped: false,
+        pipe_requests: None,
+        // Last, so the fields above can still read from it
+        config,
+    };
+
+    let message_window = create_message_window();
+    match message_window {
+        Some(hwnd) => {
+            app.tray = Tray::add(hwnd, &app.tray_tooltip());
+            if app.tray.is_none() {
+                notice!("Warning: couldn't add the tray icon");
+            }
+            if unsafe { WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) } == 0 {
+                notice!("Warning: couldn't register for session changes (error {}); the clip may stay off after Win+L",
+                    unsafe { GetLastError() });
             }
         }
+        None => notice!("Warning: couldn't create the message window; display changes won't be tracked"),
+    }
+    if args.overlay || app.config.overlay {
+        app.overlay = Overlay::create();
+        if app.overlay.is_none() {
+            notice!("Warning: couldn't create the overlay border");
+        }
+        app.update_overlay();
+    }
 
-        // Handle F11 monitor switching
-        if f11_pressed {
-            if let Some(new_rc) = get_monitor_rect_for_point(pt.x, pt.y) {
-                // Check if this is actually a different monitor
-                if let Some(cur) = &current_rect {
-                    if new_rc.left != cur.left || new_rc.top != cur.top || 
-                       new_rc.right != cur.right || new_rc.bottom != cur.bottom {
-                        unsafe { ClipCursor(&new_rc) };
-                        current_rect = Some(new_rc);
-                        clipped = true;
-                        release_on_exit = false;
-                        println!("F11 pressed: Changed lock to new monitor");
-                    }
+    let mut hotkeys = vec![
+        (HOTKEY_SWITCH, switch_hotkey),
+        (HOTKEY_TOGGLE, toggle_hotkey),
+        (HOTKEY_NEXT_MONITOR, next_hotkey),
+        (HOTKEY_PREV_MONITOR, prev_hotkey),
+        (HOTKEY_RELOAD, reload_hotkey),
+        (HOTKEY_PICK_MONITOR, pick_hotkey),
+        (HOTKEY_STATUS, status_hotkey),
+    ];
+    // Only claim the profile hotkey when there's something to cycle through
+    if !app.config.profiles.is_empty() {
+        hotkeys.push((HOTKEY_NEXT_PROFILE, profile_hotkey));
+    }
+    if app.config.favorite_monitors.is_some() {
+        hotkeys.push((HOTKEY_FAVORITE, favorite_hotkey));
+    }
+    // F10 is the menu key in most programs, so only take it when asked to
+    if app.timed_release.is_some() {
+        hotkeys.push((HOTKEY_TIMED_RELEASE, timed_release_hotkey));
+    }
+    // Bare keypad digits would be taken from every other program
+    if jump_modifiers.0 != 0 {
+        for n in 1..=9 {
+            hotkeys.push((HOTKEY_JUMP_BASE + n, Hotkey::new(jump_modifiers.0 as isize, VK_NUMPAD0 + n)));
+        }
+    }
+    if args.input_hook || app.config.input_hook {
+        match KeyHook::install() {
+            Ok(hook) => app.key_hook = Some(hook),
+            Err(code) => notice!("Warning: couldn't install the keyboard hook (error {}); polling keys instead", code),
+        }
+    }
+    for &(id, hotkey) in &hotkeys {
+        if app.key_hook.is_some() || hotkey.is_mouse_button() {
+            // Registered hotkeys don't fire while some games have focus, or
+            // for mouse buttons at all
+            app.polled_hotkeys.push((id, hotkey, KeyEdge::default()));
+            continue;
+        }
+        // MOD_NOREPEAT: holding the key down fires once, not on every auto-repeat
+        if unsafe { RegisterHotKey(ptr::null_mut(), id, hotkey.modifiers | MOD_NOREPEAT as u32, hotkey.vk) } == 0 {
+            notice!("Warning: couldn't register the {} hotkey (is another program using it?); polling it instead", hotkey);
+            app.polled_hotkeys.push((id, hotkey, KeyEdge::default()));
+        }
+    }
+
+    if args.background {
+        if !args.silent() {
+            println!("Running in the background; status messages go to the log file");
+        }
+        logging::disable_console();
+        unsafe { FreeConsole() };
+    }
+
+    let thread_id = unsafe { GetCurrentThreadId() };
+    let notify = move || unsafe {
+        PostThreadMessageW(thread_id, WM_APP_PIPE_COMMAND, 0, 0);
+    };
+    let (request_tx, request_rx) = mpsc::channel();
+    let mut serving = false;
+    if args.pipe || config_pipe {
+        pipe::spawn(request_tx.clone(), notify);
+        notice!("Listening for commands on {}", pipe::PIPE_NAME);
+        serving = true;
+    }
+    if let Some(addr) = args.http.or(app.config.http) {
+        match http::spawn(addr, request_tx, notify) {
+            Ok(()) => {
+                notice!("Listening for HTTP commands on http://{}", addr);
+                if !addr.ip().is_loopback() {
+                    notice!("Warning: the HTTP endpoint is reachable from other machines and has no authentication");
                 }
+                serving = true;
             }
+            Err(e) => notice!("Warning: couldn't listen for HTTP commands on {}: {}", addr, e),
+        }
+    }
+    if serving {
+        app.pipe_requests = Some(request_rx);
+    }
+
+    // Started by --service: its stop ends the program like Ctrl+C does
+    service::watch_stop_event(request_shutdown);
+    MAIN_THREAD.store(thread_id, Ordering::SeqCst);
+    unsafe {
+        RELEASED.store(CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null()), Ordering::SeqCst);
+        SetConsoleCtrlHandler(Some(console_ctrl_handler), TRUE);
+        // The rest of the state still has to be polled, but a timer lets the
+        // thread block in GetMessageW between checks instead of sleeping in a loop.
+        app.timer_id = SetTimer(ptr::null_mut(), 0, app.poll_interval, None);
+    }
+
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while unsafe { GetMessageW(&mut msg, ptr::null_mut(), 0, 0) } > 0 {
+        // Before any message, so a hotkey or command queued behind the
+        // shutdown can't clip again
+        if SHUTDOWN.load(Ordering::SeqCst) {
+            break;
+        }
+        match msg.message {
+            WM_TIMER => app.tick(),
+            WM_HOTKEY => app.on_hotkey(msg.wParam as i32),
+            WM_APP_DISPLAY_CHANGED => app.refresh_monitors(),
+            WM_APP_SESSION_CHANGED => app.on_session_change(msg.wParam),
+            WM_APP_TRAY_MENU => app.show_tray_menu(),
+            WM_APP_PIPE_COMMAND => app.handle_pipe_requests(),
+            _ => unsafe {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            },
         }
+    }
+
+    for (id, _) in hotkeys {
+        unsafe { UnregisterHotKey(ptr::null_mut(), id) };
+    }
+    if let Some(hwnd) = message_window {
+        unsafe { WTSUnRegisterSessionNotification(hwnd) };
+    }
+    app.locker.release();
+    let released = RELEASED.load(Ordering::SeqCst);
+    if !released.is_null() {
+        unsafe { SetEvent(released) };
+    }
+    if !app.clip_steals.is_empty() {
+        let mut steals: Vec<_> = app.clip_steals.iter().collect();
+        steals.sort_by(|a, b| b.1.cmp(a.1));
+        let summary: Vec<String> = steals.iter().map(|(name, n)| format!("{} {}", name, n)).collect();
+        status!("Clip was stolen and re-applied: {}", summary.join(", "));
+    }
+    if !instance_mutex.is_null() {
+        unsafe { CloseHandle(instance_mutex) };
+    }
+    status!("Released clip, exiting");
+    event!("exiting");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lockmousetomonitor::sort_monitors;
+    use winapi::shared::windef::HMONITOR;
+
+    fn monitor(handle: usize, rect: RECT, device_name: &str, primary: bool) -> MonitorInfo {
+        MonitorInfo::new(handle as HMONITOR, rect, rect, device_name, primary)
+    }
+
+    /// Two 1920x1080 monitors either side of a 2560x1440 primary, the left
+    /// one at a negative origin, written in enumeration order and sorted into
+    /// listing order.
+    fn layout() -> Vec<MonitorInfo> {
+        let mut monitors = vec![
+            monitor(1, RECT { left: 0, top: 0, right: 2560, bottom: 1440 }, r"\\.\DISPLAY1", true),
+            monitor(2, RECT { left: -1920, top: 360, right: 0, bottom: 1440 }, r"\\.\DISPLAY2", false),
+            monitor(3, RECT { left: 2560, top: 0, right: 4480, bottom: 1080 }, r"\\.\DISPLAY3", false),
+        ];
+        sort_monitors(&mut monitors);
+        monitors
+    }
+
+    fn resolve(value: &str) -> Result<Option<Vec<usize>>, String> {
+        resolve_selector(&value.parse().unwrap(), &layout(), None)
+    }
+
+    #[test]
+    fn numbers_follow_listing_order() {
+        assert_eq!(resolve("1"), Ok(Some(vec![0])));
+        assert_eq!(resolve("3,1"), Ok(Some(vec![2, 0])));
+        assert_eq!(resolve("all"), Ok(Some(vec![0, 1, 2])));
+        assert!(resolve("4").is_err());
+    }
+
+    #[test]
+    fn exclude_keeps_the_others() {
+        assert_eq!(resolve("exclude:2"), Ok(Some(vec![0, 2])));
+        assert!(resolve("exclude:4").is_err());
+        let single = vec![monitor(1, RECT { left: 0, top: 0, right: 1920, bottom: 1080 }, r"\\.\DISPLAY1", true)];
+        assert!(resolve_selector(&MonitorSelector::Exclude(1), &single, None).is_err());
+    }
+
+    #[test]
+    fn primary_and_current_fallback() {
+        assert_eq!(resolve("primary"), Ok(Some(vec![1])));
+        // No cursor position falls back to the primary
+        assert_eq!(resolve("current"), Ok(Some(vec![1])));
+    }
+
+    #[test]
+    fn position_with_negative_origin() {
+        assert_eq!(resolve("at:-1920,360"), Ok(Some(vec![0])));
+        assert!(resolve("at:0,360").is_err());
+    }
+
+    #[test]
+    fn resolution_must_be_unique() {
+        assert_eq!(resolve("2560x1440"), Ok(Some(vec![1])));
+        let err = resolve("1920x1080").unwrap_err();
+        assert!(err.contains("Monitors 1, 3"), "{}", err);
+        assert!(resolve("1280x720").is_err());
+    }
 
-        thread::sleep(Duration::from_millis(16)); // ~60Hz
+    #[test]
+    fn device_name_ignores_case() {
+        assert_eq!(resolve(r"device:\\.\display3"), Ok(Some(vec![2])));
     }
 }
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// How close to a monitor edge the cursor has to be to count as "at the edge".
+/// Written as a pixel count (`4`) or a percentage of the monitor size (`"1.5%"`).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "MarginRepr", into = "MarginRepr")]
+pub enum EdgeMargin {
+    Pixels(i32),
+    /// Percent of the monitor width for the left/right edges and of its height
+    /// for the top/bottom edges.
+    Percent(f64),
+}
+
+impl Default for EdgeMargin {
+    fn default() -> Self {
+        EdgeMargin::Pixels(1)
+    }
+}
+
+impl EdgeMargin {
+    /// The margin in pixels for a rect of the given size, as `(x, y)`.
+    pub fn to_pixels(self, width: i32, height: i32) -> (i32, i32) {
+        match self {
+            EdgeMargin::Pixels(px) => (px, px),
+            EdgeMargin::Percent(pct) => (
+                (width as f64 * pct / 100.0).round() as i32,
+                (height as f64 * pct / 100.0).round() as i32,
+            ),
+        }
+    }
+}
+
+impl FromStr for EdgeMargin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let margin = if let Some(pct) = s.strip_suffix('%') {
+            match pct.trim().parse::<f64>() {
+                Ok(p) if (0.0..=50.0).contains(&p) => EdgeMargin::Percent(p),
+                _ => return Err(format!("Invalid edge margin \"{}\": percentage must be between 0% and 50%", s)),
+            }
+        } else {
+            match s.strip_suffix("px").unwrap_or(s).trim().parse::<i32>() {
+                Ok(px) if px >= 0 => EdgeMargin::Pixels(px),
+                _ => return Err(format!("Invalid edge margin \"{}\": expected pixels (e.g. 4) or a percentage (e.g. 1%)", s)),
+            }
+        };
+        Ok(margin)
+    }
+}
+
+impl fmt::Display for EdgeMargin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeMargin::Pixels(px) => write!(f, "{}px", px),
+            EdgeMargin::Percent(pct) => write!(f, "{}%", pct),
+        }
+    }
+}
+
+/// Config representation: a bare integer is pixels, a string may be either.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MarginRepr {
+    Pixels(i32),
+    Text(String),
+}
+
+impl TryFrom<MarginRepr> for EdgeMargin {
+    type Error = String;
+
+    fn try_from(repr: MarginRepr) -> Result<Self, Self::Error> {
+        match repr {
+            MarginRepr::Pixels(px) if px >= 0 => Ok(EdgeMargin::Pixels(px)),
+            MarginRepr::Pixels(px) => Err(format!("Invalid edge margin {}: must not be negative", px)),
+            MarginRepr::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<EdgeMargin> for MarginRepr {
+    fn from(margin: EdgeMargin) -> Self {
+        match margin {
+            EdgeMargin::Pixels(px) => MarginRepr::Pixels(px),
+            EdgeMargin::Percent(_) => MarginRepr::Text(margin.to_string()),
+        }
+    }
+}
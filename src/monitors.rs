@@ -0,0 +1,538 @@
+//! Monitor enumeration: what Windows reports about each display, in the
+//! order the listing and `--monitor` numbers use.
+
+use crate::geometry::{point_in_rect, rects_equal};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ptr;
+use std::str::FromStr;
+use winapi::shared::minwindef::{BOOL, UINT};
+use winapi::shared::windef::{
+    DPI_AWARENESS_CONTEXT, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, HDC, HMONITOR, POINT, RECT,
+};
+use winapi::shared::winerror::ERROR_SUCCESS;
+use winapi::um::libloaderapi::{GetModuleHandleW, GetProcAddress};
+use winapi::um::wingdi::{
+    DEVMODEW, DMDO_180, DMDO_270, DMDO_90, DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+    DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO,
+    DISPLAYCONFIG_SOURCE_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME, QDC_ONLY_ACTIVE_PATHS,
+};
+use winapi::um::winnt::LONG;
+use winapi::um::winuser::{
+    EnumDisplayMonitors, EnumDisplaySettingsW, ENUM_CURRENT_SETTINGS, GetCursorPos, GetMonitorInfoW, MONITORINFO,
+    MONITORINFOEXW, MONITORINFOF_PRIMARY, SetProcessDPIAware,
+};
+
+// The display configuration API isn't bound by winapi 0.3.
+#[link(name = "user32")]
+extern "system" {
+    fn GetDisplayConfigBufferSizes(flags: UINT, num_paths: *mut UINT, num_modes: *mut UINT) -> LONG;
+    fn QueryDisplayConfig(
+        flags: UINT,
+        num_paths: *mut UINT,
+        paths: *mut DISPLAYCONFIG_PATH_INFO,
+        num_modes: *mut UINT,
+        modes: *mut DISPLAYCONFIG_MODE_INFO,
+        topology: *mut UINT,
+    ) -> LONG;
+    fn DisplayConfigGetDeviceInfo(packet: *mut DISPLAYCONFIG_DEVICE_INFO_HEADER) -> LONG;
+}
+
+#[derive(Clone)]
+pub struct MonitorInfo {
+    pub(crate) handle: HMONITOR,
+    pub(crate) rect: RECT,
+    pub(crate) work_rect: RECT,
+    pub(crate) device_name: String,
+    pub(crate) friendly_name: Option<String>,
+    pub(crate) device_path: Option<String>,
+    pub(crate) orientation: u32,
+    pub(crate) primary: bool,
+}
+
+impl MonitorInfo {
+    /// A monitor that doesn't come from enumeration, for a
+    /// [`MonitorSource`] with a made-up layout. Each needs its own `handle`,
+    /// as the locker tells monitors apart by it; any distinct non-null
+    /// values do.
+    pub fn new(handle: HMONITOR, rect: RECT, work_rect: RECT, device_name: &str, primary: bool) -> Self {
+        MonitorInfo {
+            handle,
+            rect,
+            work_rect,
+            device_name: device_name.to_string(),
+            friendly_name: None,
+            device_path: None,
+            orientation: 0,
+            primary,
+        }
+    }
+
+    pub fn handle(&self) -> HMONITOR {
+        self.handle
+    }
+
+    /// The full monitor rect in virtual-desktop coordinates.
+    pub fn rect(&self) -> RECT {
+        self.rect
+    }
+
+    /// The monitor rect minus the taskbar and other app bars.
+    pub fn work_rect(&self) -> RECT {
+        self.work_rect
+    }
+
+    /// The GDI device name, like `\\.\DISPLAY1`.
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// The monitor's own name from its EDID, like `DELL U2720Q`, if Windows
+    /// reports one.
+    pub fn friendly_name(&self) -> Option<&str> {
+        self.friendly_name.as_deref()
+    }
+
+    /// The monitor's device interface path, like
+    /// `\\?\DISPLAY#DEL4109#5&1a2b3c4d&0&UID4357#{e6f07b5f-...}`. It belongs to
+    /// the monitor itself, so it stays the same across reboots and when the
+    /// displays are rearranged.
+    pub fn device_path(&self) -> Option<&str> {
+        self.device_path.as_deref()
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// How far the display is rotated clockwise, in degrees: 0, 90, 180 or
+    /// 270.
+    pub fn orientation(&self) -> u32 {
+        self.orientation
+    }
+
+    /// Taller than it is wide, whether from rotation or the panel itself.
+    pub fn is_portrait(&self) -> bool {
+        self.rect.bottom - self.rect.top > self.rect.right - self.rect.left
+    }
+}
+
+fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// What the display configuration knows about the monitor on a GDI device.
+#[derive(Default)]
+struct DisplayTarget {
+    friendly_name: Option<String>,
+    device_path: Option<String>,
+}
+
+/// Maps GDI device names to the EDID friendly names and device paths of the
+/// active displays.
+fn display_targets() -> HashMap<String, DisplayTarget> {
+    let mut targets = HashMap::new();
+    unsafe {
+        let (mut num_paths, mut num_modes) = (0, 0);
+        if GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut num_paths, &mut num_modes) != ERROR_SUCCESS as LONG {
+            return targets;
+        }
+        let mut paths: Vec<DISPLAYCONFIG_PATH_INFO> = vec![std::mem::zeroed(); num_paths as usize];
+        let mut modes: Vec<DISPLAYCONFIG_MODE_INFO> = vec![std::mem::zeroed(); num_modes as usize];
+        if QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut num_paths,
+            paths.as_mut_ptr(),
+            &mut num_modes,
+            modes.as_mut_ptr(),
+            ptr::null_mut(),
+        ) != ERROR_SUCCESS as LONG {
+            return targets;
+        }
+        paths.truncate(num_paths as usize);
+
+        for path in &paths {
+            let mut source: DISPLAYCONFIG_SOURCE_DEVICE_NAME = std::mem::zeroed();
+            source.header._type = DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME;
+            source.header.size = std::mem::size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>() as u32;
+            source.header.adapterId = path.sourceInfo.adapterId;
+            source.header.id = path.sourceInfo.id;
+            if DisplayConfigGetDeviceInfo(&mut source.header) != ERROR_SUCCESS as LONG {
+                continue;
+            }
+
+            let mut target: DISPLAYCONFIG_TARGET_DEVICE_NAME = std::mem::zeroed();
+            target.header._type = DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME;
+            target.header.size = std::mem::size_of::<DISPLAYCONFIG_TARGET_DEVICE_NAME>() as u32;
+            target.header.adapterId = path.targetInfo.adapterId;
+            target.header.id = path.targetInfo.id;
+            if DisplayConfigGetDeviceInfo(&mut target.header) != ERROR_SUCCESS as LONG {
+                continue;
+            }
+
+            // A mirrored source has several targets; keep the first one
+            targets.entry(wide_to_string(&source.viewGdiDeviceName)).or_insert_with(|| {
+                let non_empty = |s: String| (!s.is_empty()).then_some(s);
+                DisplayTarget {
+                    friendly_name: non_empty(wide_to_string(&target.monitorFriendlyDeviceName)),
+                    device_path: non_empty(wide_to_string(&target.monitorDevicePath)),
+                }
+            });
+        }
+    }
+    targets
+}
+
+/// The rotation of the display attached to a GDI device, in degrees; 0 if
+/// its settings can't be read.
+unsafe fn display_orientation(device_name: &[u16]) -> u32 {
+    let mut mode: DEVMODEW = std::mem::zeroed();
+    mode.dmSize = std::mem::size_of::<DEVMODEW>() as u16;
+    if EnumDisplaySettingsW(device_name.as_ptr(), ENUM_CURRENT_SETTINGS, &mut mode) == 0 {
+        return 0;
+    }
+    match mode.u1.s2().dmDisplayOrientation {
+        DMDO_90 => 90,
+        DMDO_180 => 180,
+        DMDO_270 => 270,
+        _ => 0,
+    }
+}
+
+unsafe extern "system" fn monitor_enum_proc(
+    hmonitor: HMONITOR,
+    _: HDC,
+    _: *mut RECT,
+    data: isize,
+) -> BOOL {
+    let monitors = &mut *(data as *mut Vec<MonitorInfo>);
+    let mut mi: MONITORINFOEXW = std::mem::zeroed();
+    mi.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    
+    if GetMonitorInfoW(hmonitor, &mut mi as *mut MONITORINFOEXW as *mut MONITORINFO) != 0 {
+        monitors.push(MonitorInfo {
+            handle: hmonitor,
+            rect: mi.rcMonitor,
+            work_rect: mi.rcWork,
+            device_name: wide_to_string(&mi.szDevice),
+            friendly_name: None,
+            device_path: None,
+            orientation: display_orientation(&mi.szDevice),
+            primary: mi.dwFlags & MONITORINFOF_PRIMARY != 0,
+        });
+    }
+    1 // continue enumeration
+}
+
+/// Lists all monitors, sorted left to right.
+pub fn get_all_monitors() -> Vec<MonitorInfo> {
+    get_all_monitors_by(MonitorOrder::Left)
+}
+
+/// Lists all monitors in `order`.
+pub fn get_all_monitors_by(order: MonitorOrder) -> Vec<MonitorInfo> {
+    let mut monitors = enumerate_monitors();
+    sort_monitors_by(&mut monitors, order);
+    monitors
+}
+
+/// The monitors in the order `EnumDisplayMonitors` reports them.
+fn enumerate_monitors() -> Vec<MonitorInfo> {
+    let mut monitors: Vec<MonitorInfo> = Vec::new();
+    let monitors_ptr = &mut monitors as *mut Vec<MonitorInfo>;
+    unsafe {
+        EnumDisplayMonitors(
+            ptr::null_mut(),
+            ptr::null(),
+            Some(monitor_enum_proc),
+            monitors_ptr as isize,
+        );
+    }
+    let mut targets = display_targets();
+    for monitor in &mut monitors {
+        let target = targets.remove(&monitor.device_name).unwrap_or_default();
+        monitor.friendly_name = target.friendly_name;
+        monitor.device_path = target.device_path;
+    }
+    monitors
+}
+
+/// Where a [`MonitorLocker`](crate::MonitorLocker) gets its monitors, both at
+/// creation and on every refresh. [`SystemMonitors`] asks Windows; a
+/// `Vec<MonitorInfo>` is a fixed layout, for testing the selection and
+/// clamping without the displays to match.
+pub trait MonitorSource {
+    /// The monitors in their natural order (Windows' enumeration order, or
+    /// the vector's). The locker sorts them by its [`MonitorOrder`].
+    fn monitors(&self) -> Vec<MonitorInfo>;
+}
+
+/// The real monitors, as Windows enumerates them.
+pub struct SystemMonitors;
+
+impl MonitorSource for SystemMonitors {
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        enumerate_monitors()
+    }
+}
+
+impl MonitorSource for Vec<MonitorInfo> {
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        self.clone()
+    }
+}
+
+/// How monitors are numbered, in the listing and for `--monitor`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorOrder {
+    /// Left to right by the left edge.
+    #[default]
+    Left,
+    /// Top to bottom by the top edge, then left to right, for stacked monitors.
+    Top,
+    /// By device name, so `\\.\DISPLAY2` comes before `\\.\DISPLAY10`.
+    Name,
+    /// In the order Windows enumerates them.
+    System,
+}
+
+impl FromStr for MonitorOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "left" => Ok(MonitorOrder::Left),
+            "top" => Ok(MonitorOrder::Top),
+            "name" => Ok(MonitorOrder::Name),
+            "system" => Ok(MonitorOrder::System),
+            _ => Err(format!("Invalid monitor order \"{}\": expected left, top, name or system", s)),
+        }
+    }
+}
+
+/// Puts monitors in listing order: left to right by their left edge. The
+/// sort is stable, so monitors stacked in one column keep the order Windows
+/// enumerated them in.
+pub fn sort_monitors(monitors: &mut [MonitorInfo]) {
+    sort_monitors_by(monitors, MonitorOrder::Left);
+}
+
+/// Puts monitors in `order`. Like [`sort_monitors`] the sort is stable, so
+/// ties keep the order they came in.
+pub fn sort_monitors_by(monitors: &mut [MonitorInfo], order: MonitorOrder) {
+    match order {
+        MonitorOrder::Left => monitors.sort_by_key(|m| m.rect.left),
+        MonitorOrder::Top => monitors.sort_by_key(|m| (m.rect.top, m.rect.left)),
+        MonitorOrder::Name => monitors.sort_by(|a, b| name_key(&a.device_name).cmp(&name_key(&b.device_name))),
+        MonitorOrder::System => {}
+    }
+}
+
+/// A device name split into its prefix and trailing number, so the numbers
+/// compare as numbers.
+fn name_key(name: &str) -> (&str, u64) {
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    (prefix, name[prefix.len()..].parse().unwrap_or(0))
+}
+
+/// Index of the monitor in `monitors` that this one is a mirror clone of.
+///
+/// Clones come from display duplication or from drivers that report a pseudo
+/// monitor on top of a real one. A monitor that reuses an earlier monitor's
+/// GDI device name is the same display reported twice. Otherwise a monitor
+/// counts as a clone when its rect lies entirely inside another's, or
+/// matches an earlier one's exactly. Displays that merely have the same size
+/// sit side by side without overlapping, so they're never matched.
+pub fn mirror_of(monitors: &[MonitorInfo], idx: usize) -> Option<usize> {
+    let monitor = &monitors[idx];
+    let rc = &monitor.rect;
+    monitors.iter().enumerate().position(|(j, other)| {
+        if j == idx {
+            return false;
+        }
+        if j < idx && other.device_name == monitor.device_name {
+            return true;
+        }
+        let orc = &other.rect;
+        if rects_equal(rc, orc) {
+            return j < idx;
+        }
+        orc.left <= rc.left && orc.top <= rc.top && orc.right >= rc.right && orc.bottom >= rc.bottom
+    })
+}
+
+/// Drops every mirror clone, keeping the monitors they duplicate.
+pub fn remove_mirrors(monitors: &mut Vec<MonitorInfo>) {
+    let keep: Vec<bool> = (0..monitors.len()).map(|i| mirror_of(monitors, i).is_none()).collect();
+    let mut keep = keep.into_iter();
+    monitors.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Index of the monitor in `monitors` whose rect contains `pt`.
+pub fn monitor_index_at(monitors: &[MonitorInfo], pt: &POINT) -> Option<usize> {
+    monitors.iter().position(|m| point_in_rect(pt, &m.rect))
+}
+
+/// Index of the monitor in `monitors` that contains the cursor.
+pub fn get_current_monitor_index(monitors: &[MonitorInfo]) -> Option<usize> {
+    unsafe {
+        let mut pt: POINT = std::mem::zeroed();
+        if GetCursorPos(&mut pt) == 0 {
+            return None;
+        }
+        monitor_index_at(monitors, &pt)
+    }
+}
+
+/// Index of the primary monitor in `monitors`.
+pub fn get_primary_monitor_index(monitors: &[MonitorInfo]) -> Option<usize> {
+    monitors.iter().position(|m| m.primary)
+}
+
+/// Declares the process per-monitor DPI aware, so monitor rects and cursor
+/// positions are in physical pixels even next to a scaled display. Call it
+/// before enumerating monitors. Windows older than 10 1703 lack the V2 context
+/// and get system DPI awareness instead. Returns false if neither took.
+pub fn enable_dpi_awareness() -> bool {
+    type SetProcessDpiAwarenessContext = unsafe extern "system" fn(DPI_AWARENESS_CONTEXT) -> BOOL;
+    let user32: Vec<u16> = "user32.dll\0".encode_utf16().collect();
+    unsafe {
+        let module = GetModuleHandleW(user32.as_ptr());
+        let set_context = GetProcAddress(module, c"SetProcessDpiAwarenessContext".as_ptr());
+        if !set_context.is_null() {
+            let set_context: SetProcessDpiAwarenessContext = std::mem::transmute(set_context);
+            if set_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) != 0 {
+                return true;
+            }
+        }
+        SetProcessDPIAware() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(left: i32, top: i32, right: i32, bottom: i32, device_name: &str) -> MonitorInfo {
+        let rect = RECT { left, top, right, bottom };
+        MonitorInfo {
+            handle: ptr::null_mut(),
+            rect,
+            work_rect: rect,
+            device_name: device_name.to_string(),
+            friendly_name: None,
+            device_path: None,
+            orientation: 0,
+            primary: left == 0 && top == 0,
+        }
+    }
+
+    fn names(monitors: &[MonitorInfo]) -> Vec<&str> {
+        monitors.iter().map(|m| m.device_name()).collect()
+    }
+
+    #[test]
+    fn sort_monitors_left_to_right() {
+        let mut monitors = vec![
+            monitor(1920, 0, 3840, 1080, "B"),
+            monitor(-2560, 0, 0, 1440, "L"),
+            monitor(0, 0, 1920, 1080, "A"),
+        ];
+        sort_monitors(&mut monitors);
+        assert_eq!(names(&monitors), ["L", "A", "B"]);
+    }
+
+    #[test]
+    fn sort_monitors_keeps_stacked_order() {
+        let mut monitors = vec![monitor(0, -1080, 1920, 0, "top"), monitor(0, 0, 1920, 1080, "bottom")];
+        sort_monitors(&mut monitors);
+        assert_eq!(names(&monitors), ["top", "bottom"]);
+    }
+
+    #[test]
+    fn sort_monitors_by_top_then_left() {
+        let mut monitors = vec![
+            monitor(0, 0, 1920, 1080, "bottom"),
+            monitor(1920, -1080, 3840, 0, "top right"),
+            monitor(0, -1080, 1920, 0, "top left"),
+        ];
+        sort_monitors_by(&mut monitors, MonitorOrder::Top);
+        assert_eq!(names(&monitors), ["top left", "top right", "bottom"]);
+    }
+
+    #[test]
+    fn sort_monitors_by_name_compares_numbers() {
+        let mut monitors = vec![
+            monitor(0, 0, 1920, 1080, r"\\.\DISPLAY10"),
+            monitor(1920, 0, 3840, 1080, r"\\.\DISPLAY2"),
+            monitor(-1920, 0, 0, 1080, r"\\.\DISPLAY1"),
+        ];
+        sort_monitors_by(&mut monitors, MonitorOrder::Name);
+        assert_eq!(names(&monitors), [r"\\.\DISPLAY1", r"\\.\DISPLAY2", r"\\.\DISPLAY10"]);
+    }
+
+    #[test]
+    fn sort_monitors_by_system_keeps_order() {
+        let mut monitors = vec![monitor(1920, 0, 3840, 1080, "B"), monitor(0, 0, 1920, 1080, "A")];
+        sort_monitors_by(&mut monitors, MonitorOrder::System);
+        assert_eq!(names(&monitors), ["B", "A"]);
+    }
+
+    #[test]
+    fn monitor_order_parses() {
+        assert_eq!("Top".parse::<MonitorOrder>(), Ok(MonitorOrder::Top));
+        assert_eq!("system".parse::<MonitorOrder>(), Ok(MonitorOrder::System));
+        assert!("right".parse::<MonitorOrder>().is_err());
+    }
+
+    #[test]
+    fn monitor_index_at_finds_containing_monitor() {
+        let monitors = [monitor(-1920, 0, 0, 1080, "L"), monitor(0, 0, 1920, 1080, "A")];
+        assert_eq!(monitor_index_at(&monitors, &POINT { x: -1, y: 500 }), Some(0));
+        assert_eq!(monitor_index_at(&monitors, &POINT { x: 0, y: 500 }), Some(1));
+        assert_eq!(monitor_index_at(&monitors, &POINT { x: 1920, y: 500 }), None);
+    }
+
+    #[test]
+    fn mirror_of_finds_clones() {
+        let monitors = [
+            monitor(0, 0, 1920, 1080, "A"),
+            monitor(0, 0, 1920, 1080, "B"),
+            monitor(0, 0, 1280, 720, "C"),
+            monitor(1920, 0, 3840, 1080, "D"),
+            monitor(3840, 0, 5760, 1080, "A"),
+        ];
+        assert_eq!(mirror_of(&monitors, 0), None);
+        assert_eq!(mirror_of(&monitors, 1), Some(0));
+        assert_eq!(mirror_of(&monitors, 2), Some(0));
+        // Same size as the first, but beside it
+        assert_eq!(mirror_of(&monitors, 3), None);
+        // Same device reported twice
+        assert_eq!(mirror_of(&monitors, 4), Some(0));
+    }
+
+    #[test]
+    fn remove_mirrors_keeps_originals() {
+        let mut monitors = vec![
+            monitor(0, 0, 1280, 720, "small"),
+            monitor(0, 0, 1920, 1080, "big"),
+            monitor(1920, 0, 3840, 1080, "right"),
+        ];
+        remove_mirrors(&mut monitors);
+        assert_eq!(names(&monitors), ["big", "right"]);
+    }
+
+    #[test]
+    fn vec_source_keeps_its_order() {
+        let layout = vec![monitor(0, 0, 1920, 1080, "A"), monitor(-1080, -420, 0, 1500, "L")];
+        assert_eq!(names(&layout.monitors()), ["A", "L"]);
+    }
+
+    #[test]
+    fn primary_index() {
+        let monitors = [monitor(-1920, 0, 0, 1080, "L"), monitor(0, 0, 1920, 1080, "A")];
+        assert_eq!(get_primary_monitor_index(&monitors), Some(1));
+    }
+}
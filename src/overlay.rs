@@ -0,0 +1,95 @@
+//! `--overlay`: a thin border around the locked monitor.
+//!
+//! The border is a topmost popup whose window region is just the frame, so
+//! the class brush paints it and nothing has to handle `WM_PAINT`.
+//! `WS_EX_LAYERED | WS_EX_TRANSPARENT` makes it click-through: mouse input
+//! goes to whatever is underneath, as if the border weren't there.
+
+use lockmousetomonitor::rects_equal;
+use std::ptr;
+use winapi::shared::windef::{HWND, RECT};
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::wingdi::{CombineRgn, CreateRectRgn, CreateSolidBrush, DeleteObject, RGB, RGN_DIFF};
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, RegisterClassW, SetLayeredWindowAttributes, SetWindowPos,
+    SetWindowRgn, ShowWindow, HWND_TOPMOST, LWA_ALPHA, SWP_NOACTIVATE, SW_HIDE, SW_SHOWNOACTIVATE, WNDCLASSW,
+    WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+};
+
+/// Border thickness in pixels.
+const BORDER_WIDTH: i32 = 3;
+
+/// The window is destroyed again when this is dropped.
+pub struct Overlay {
+    hwnd: HWND,
+    /// The rect the border is around, `None` while hidden.
+    shown: Option<RECT>,
+}
+
+impl Overlay {
+    /// Creates the (hidden) border window.
+    pub fn create() -> Option<Overlay> {
+        let class_name: Vec<u16> = "LockMouseToMonitorOverlay\0".encode_utf16().collect();
+        unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            let mut wc: WNDCLASSW = std::mem::zeroed();
+            wc.lpfnWndProc = Some(DefWindowProcW);
+            wc.hInstance = instance;
+            wc.lpszClassName = class_name.as_ptr();
+            wc.hbrBackground = CreateSolidBrush(RGB(255, 140, 0));
+            if RegisterClassW(&wc) == 0 {
+                return None;
+            }
+            let hwnd = CreateWindowExW(
+                WS_EX_LAYERED | WS_EX_TRANSPARENT | WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                WS_POPUP,
+                0, 0, 0, 0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                instance,
+                ptr::null_mut(),
+            );
+            if hwnd.is_null() {
+                return None;
+            }
+            // A layered window isn't drawn at all until its attributes are set
+            SetLayeredWindowAttributes(hwnd, 0, 255, LWA_ALPHA);
+            Some(Overlay { hwnd, shown: None })
+        }
+    }
+
+    /// Puts the border around `rect`, or hides it for `None`. Cheap to call
+    /// when nothing changed.
+    pub fn show(&mut self, rect: Option<RECT>) {
+        match (self.shown, rect) {
+            (Some(a), Some(b)) if rects_equal(&a, &b) => return,
+            (None, None) => return,
+            _ => {}
+        }
+        self.shown = rect;
+        let Some(rc) = rect else {
+            unsafe { ShowWindow(self.hwnd, SW_HIDE) };
+            return;
+        };
+        let (width, height) = (rc.right - rc.left, rc.bottom - rc.top);
+        unsafe {
+            // The window owns the region once it's set, so only the inner
+            // one is deleted here
+            let frame = CreateRectRgn(0, 0, width, height);
+            let inner = CreateRectRgn(BORDER_WIDTH, BORDER_WIDTH, width - BORDER_WIDTH, height - BORDER_WIDTH);
+            CombineRgn(frame, frame, inner, RGN_DIFF);
+            DeleteObject(inner as _);
+            SetWindowPos(self.hwnd, HWND_TOPMOST, rc.left, rc.top, width, height, SWP_NOACTIVATE);
+            SetWindowRgn(self.hwnd, frame, 1);
+            ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+        }
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        unsafe { DestroyWindow(self.hwnd) };
+    }
+}
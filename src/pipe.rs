@@ -0,0 +1,120 @@
+//! Named pipe for controlling a running instance, e.g. from a Stream Deck.
+//!
+//! Each connection sends one command line and gets one reply line:
+//! `LOCK <n>`, `RELEASE`, `TOGGLE`, `RELOAD` or `STATUS`. The server runs on its own
+//! thread; commands are handed to the main loop, which owns the lock.
+
+use std::str::FromStr;
+use std::sync::mpsc::{self, Sender};
+use std::{ptr, thread};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::fileapi::{FlushFileBuffers, ReadFile, WriteFile};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe};
+use winapi::um::winbase::{
+    PIPE_ACCESS_DUPLEX, PIPE_READMODE_MESSAGE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_MESSAGE, PIPE_WAIT,
+};
+
+pub const PIPE_NAME: &str = r"\\.\pipe\LockMouseToMonitor";
+
+const BUFFER_SIZE: DWORD = 512;
+
+pub enum Command {
+    /// Lock to this 1-based monitor number.
+    Lock(usize),
+    /// Turn the lock off.
+    Release,
+    Toggle,
+    /// Re-read the config file.
+    Reload,
+    Status,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default().to_ascii_uppercase();
+        let command = match command.as_str() {
+            "LOCK" => match words.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => Command::Lock(n),
+                _ => return Err("LOCK needs a monitor number".to_string()),
+            },
+            "RELEASE" => Command::Release,
+            "TOGGLE" => Command::Toggle,
+            "RELOAD" => Command::Reload,
+            "STATUS" => Command::Status,
+            _ => return Err(format!("unknown command \"{}\"", line.trim())),
+        };
+        if words.next().is_some() {
+            return Err(format!("unexpected arguments in \"{}\"", line.trim()));
+        }
+        Ok(command)
+    }
+}
+
+/// A command waiting for the main loop, which sends the reply line back.
+pub struct Request {
+    pub command: Result<Command, String>,
+    pub reply: Sender<String>,
+}
+
+/// Starts the server thread, queueing commands on `requests`. `notify` is
+/// called after each request is queued so the main loop can wake up for it.
+pub fn spawn(requests: Sender<Request>, notify: impl Fn() + Send + 'static) {
+    thread::spawn(move || serve(requests, notify));
+}
+
+fn serve(requests: Sender<Request>, notify: impl Fn()) {
+    let name: Vec<u16> = PIPE_NAME.encode_utf16().chain(Some(0)).collect();
+    loop {
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_MESSAGE | PIPE_READMODE_MESSAGE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if pipe == INVALID_HANDLE_VALUE {
+            status!("Couldn't create the control pipe (error {})", unsafe { GetLastError() });
+            return;
+        }
+        let connected = unsafe { ConnectNamedPipe(pipe, ptr::null_mut()) } != 0
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        if connected {
+            let mut buffer = [0u8; BUFFER_SIZE as usize];
+            let mut read = 0;
+            if unsafe { ReadFile(pipe, buffer.as_mut_ptr().cast(), BUFFER_SIZE, &mut read, ptr::null_mut()) } != 0 {
+                let line = String::from_utf8_lossy(&buffer[..read as usize]).into_owned();
+                let (reply_tx, reply_rx) = mpsc::channel();
+                let request = Request { command: line.parse(), reply: reply_tx };
+                if requests.send(request).is_err() {
+                    // The main loop is gone
+                    unsafe { CloseHandle(pipe) };
+                    return;
+                }
+                notify();
+                if let Ok(reply) = reply_rx.recv() {
+                    let reply = reply + "\n";
+                    let mut written = 0;
+                    unsafe {
+                        WriteFile(pipe, reply.as_ptr().cast(), reply.len() as DWORD, &mut written, ptr::null_mut());
+                        FlushFileBuffers(pipe);
+                    }
+                }
+            }
+        }
+        unsafe {
+            DisconnectNamedPipe(pipe);
+            CloseHandle(pipe);
+        }
+    }
+}
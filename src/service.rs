@@ -0,0 +1,405 @@
+//! `--service`: run under the Service Control Manager, so the lock starts at
+//! boot without anyone logging in to start it.
+//!
+//! Services live in session 0, which has no desktop anyone sees: a
+//! `ClipCursor` there confines nobody. So the service only supervises. It
+//! starts a copy of the program (its own command line minus `--service`, plus
+//! `--daemon`) in the active console session as the user logged in there, and
+//! starts a fresh one whenever the console session changes (fast user
+//! switching, RDP taking over the console) or the copy exits. The service's
+//! own SYSTEM token is only used with `--logon-screen`, for a copy on the
+//! sign-in screen until someone logs in. On stop it sets a named event the
+//! copy watches, and the copy releases the clip and exits just as on Ctrl+C.
+
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::time::{Duration, Instant};
+use std::{env, ptr, thread};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{DWORD, FALSE, LPVOID, TRUE};
+use winapi::shared::sddl::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+use winapi::shared::winerror::{ERROR_CALL_NOT_IMPLEMENTED, NO_ERROR};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::minwinbase::{SECURITY_ATTRIBUTES, STILL_ACTIVE};
+use winapi::um::processthreadsapi::{
+    CreateProcessAsUserW, GetCurrentProcess, GetExitCodeProcess, OpenProcessToken, TerminateProcess,
+    PROCESS_INFORMATION, STARTUPINFOW,
+};
+use winapi::um::securitybaseapi::{DuplicateTokenEx, SetTokenInformation};
+use winapi::um::synchapi::{CreateEventW, OpenEventW, SetEvent, WaitForSingleObject};
+use winapi::um::userenv::{CreateEnvironmentBlock, DestroyEnvironmentBlock};
+use winapi::um::winbase::{
+    LocalFree, WTSGetActiveConsoleSessionId, CREATE_NO_WINDOW, CREATE_UNICODE_ENVIRONMENT, INFINITE, WAIT_OBJECT_0,
+};
+use winapi::um::winnt::{
+    SecurityImpersonation, TokenPrimary, TokenSessionId, EVENT_MODIFY_STATE, HANDLE, MAXIMUM_ALLOWED,
+    SERVICE_WIN32_OWN_PROCESS, SYNCHRONIZE, TOKEN_ADJUST_DEFAULT, TOKEN_ADJUST_SESSIONID, TOKEN_ASSIGN_PRIMARY,
+    TOKEN_DUPLICATE, TOKEN_QUERY,
+};
+use winapi::um::winsvc::{
+    RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW, SERVICE_ACCEPT_SHUTDOWN,
+    SERVICE_ACCEPT_STOP, SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP,
+    SERVICE_RUNNING, SERVICE_STATUS, SERVICE_STATUS_HANDLE, SERVICE_STATUS_HANDLE__, SERVICE_STOPPED,
+    SERVICE_STOP_PENDING, SERVICE_TABLE_ENTRYW,
+};
+use winapi::um::wtsapi32::WTSQueryUserToken;
+
+use crate::EXIT_ALREADY_RUNNING;
+
+/// Set in the copy's environment to the name of the event that tells it to exit.
+pub const STOP_EVENT_ENV: &str = "LOCKMOUSE_SERVICE_STOP";
+
+const SERVICE_NAME: &str = "LockMouseToMonitor";
+const STOP_EVENT_NAME: &str = "Global\\LockMouseToMonitor.ServiceStop";
+/// Full access for SYSTEM, and enough for any logged-in user's copy to wait
+/// on the stop event (`SYNCHRONIZE`); the default one would only let SYSTEM
+/// and administrators open it.
+const STOP_EVENT_SDDL: &str = "D:(A;;GA;;;SY)(A;;0x100000;;;AU)";
+
+/// The desktops a copy runs on: the user's, and the sign-in screen's.
+const USER_DESKTOP: &str = "winsta0\\default";
+const LOGON_DESKTOP: &str = "winsta0\\winlogon";
+
+/// How often the console session and the copy are checked on.
+const SUPERVISE_INTERVAL_MS: DWORD = 1000;
+/// Waits before starting a copy again after it crashed: doubling from the
+/// first to the last, and back to the first once a copy has lasted as long.
+const RESPAWN_DELAY_MIN: Duration = Duration::from_secs(1);
+const RESPAWN_DELAY_MAX: Duration = Duration::from_secs(60);
+/// How long the copy gets to release the clip and exit before it's killed.
+const CHILD_EXIT_TIMEOUT_MS: DWORD = 5000;
+
+/// `WTSGetActiveConsoleSessionId` while the console is between sessions.
+const NO_CONSOLE_SESSION: DWORD = 0xFFFF_FFFF;
+
+/// The status handle and the event the control handler sets on stop, which
+/// the supervising loop waits on.
+static STATUS_HANDLE: AtomicPtr<SERVICE_STATUS_HANDLE__> = AtomicPtr::new(ptr::null_mut());
+static SERVICE_STOP: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+/// `--logon-screen`: run a SYSTEM copy on the sign-in screen while nobody is
+/// logged in at the console.
+static LOGON_SCREEN: AtomicBool = AtomicBool::new(false);
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Hands the thread to the Service Control Manager until the service stops.
+/// `logon_screen` also locks the sign-in screen, see the module docs. Fails
+/// with the `GetLastError` code when the process wasn't started as a service
+/// (1063 when run from a console).
+pub fn run(logon_screen: bool) -> Result<(), u32> {
+    LOGON_SCREEN.store(logon_screen, Ordering::SeqCst);
+    let mut name = wide(SERVICE_NAME);
+    let table = [
+        SERVICE_TABLE_ENTRYW { lpServiceName: name.as_mut_ptr(), lpServiceProc: Some(service_main) },
+        SERVICE_TABLE_ENTRYW { lpServiceName: ptr::null_mut(), lpServiceProc: None },
+    ];
+    if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        return Err(unsafe { GetLastError() });
+    }
+    Ok(())
+}
+
+fn set_state(state: DWORD) {
+    let handle = STATUS_HANDLE.load(Ordering::SeqCst);
+    if handle.is_null() {
+        return;
+    }
+    let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+    status.dwServiceType = SERVICE_WIN32_OWN_PROCESS;
+    status.dwCurrentState = state;
+    status.dwControlsAccepted = if state == SERVICE_RUNNING { SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN } else { 0 };
+    status.dwWin32ExitCode = NO_ERROR;
+    status.dwWaitHint = if state == SERVICE_STOP_PENDING { CHILD_EXIT_TIMEOUT_MS * 2 } else { 0 };
+    unsafe { SetServiceStatus(handle as SERVICE_STATUS_HANDLE, &mut status) };
+}
+
+unsafe extern "system" fn control_handler(control: DWORD, _event: DWORD, _data: LPVOID, _context: LPVOID) -> DWORD {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            set_state(SERVICE_STOP_PENDING);
+            SetEvent(SERVICE_STOP.load(Ordering::SeqCst));
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut *mut u16) {
+    let name = wide(SERVICE_NAME);
+    let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), ptr::null_mut());
+    if handle.is_null() {
+        return;
+    }
+    STATUS_HANDLE.store(handle, Ordering::SeqCst);
+    SERVICE_STOP.store(CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null()), Ordering::SeqCst);
+    // Manual reset, so every copy started along the way sees it once it's set
+    let event_name = wide(STOP_EVENT_NAME);
+    let sddl = wide(STOP_EVENT_SDDL);
+    let mut sa: SECURITY_ATTRIBUTES = std::mem::zeroed();
+    sa.nLength = std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD;
+    if ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1 as DWORD, &mut sa.lpSecurityDescriptor, ptr::null_mut()) == 0 {
+        status!("Service: couldn't build the stop event's permissions (error {})", GetLastError());
+    }
+    let child_stop = CreateEventW(&mut sa, TRUE, FALSE, event_name.as_ptr());
+    if child_stop.is_null() {
+        status!("Service: couldn't create the stop event (error {})", GetLastError());
+    }
+    if !sa.lpSecurityDescriptor.is_null() {
+        LocalFree(sa.lpSecurityDescriptor);
+    }
+    env::set_var(STOP_EVENT_ENV, STOP_EVENT_NAME);
+    set_state(SERVICE_RUNNING);
+    status!("Service started");
+
+    supervise(&child_command_line());
+
+    if !child_stop.is_null() {
+        CloseHandle(child_stop);
+    }
+    status!("Service stopped");
+    set_state(SERVICE_STOPPED);
+}
+
+/// What the copy runs: this executable with the service's own arguments,
+/// minus `--service`, and `--daemon` so it never waits for a prompt.
+fn child_command_line() -> Vec<u16> {
+    let quote = |arg: String| if arg.contains(' ') || arg.is_empty() { format!("\"{}\"", arg) } else { arg };
+    let exe = env::current_exe().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut parts = vec![quote(exe)];
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != "--service" && arg != "--logon-screen").collect();
+    if !args.iter().any(|arg| arg == "--daemon") {
+        parts.push("--daemon".to_string());
+    }
+    parts.extend(args.into_iter().map(quote));
+    wide(&parts.join(" "))
+}
+
+/// The copy in the console session, which session that is, and whether it
+/// runs as the logged-in user or on the sign-in screen.
+struct Child {
+    process: HANDLE,
+    session: DWORD,
+    as_user: bool,
+    started: Instant,
+}
+
+fn supervise(command_line: &[u16]) {
+    let stop = SERVICE_STOP.load(Ordering::SeqCst);
+    let mut child: Option<Child> = None;
+    // The session last logged as having nobody to run the lock for
+    let mut waiting_in = None;
+    // The session whose copy exited on purpose, which is left alone until
+    // the console moves or its user logs off
+    let mut left_alone: Option<DWORD> = None;
+    let mut respawn_delay = RESPAWN_DELAY_MIN;
+    let mut respawn_at: Option<Instant> = None;
+    loop {
+        let session = unsafe { WTSGetActiveConsoleSessionId() };
+        if left_alone.is_some_and(|s| s != session || user_token(s).map(|token| unsafe { CloseHandle(token) }).is_none()) {
+            left_alone = None;
+        }
+        if let Some(c) = &child {
+            let mut code: DWORD = 0;
+            let running = unsafe { GetExitCodeProcess(c.process, &mut code) } != 0 && code == STILL_ACTIVE;
+            let logged_in = !c.as_user && user_token(session).map(|token| unsafe { CloseHandle(token) }).is_some();
+            if !running || c.session != session || logged_in {
+                if running && logged_in {
+                    status!("Service: someone logged in to session {}; restarting the lock as them", session);
+                    unsafe { TerminateProcess(c.process, 0) };
+                } else if running {
+                    status!("Service: console moved from session {} to {}; restarting there", c.session, session);
+                    unsafe { TerminateProcess(c.process, 0) };
+                } else {
+                    status!("Service: the lock in session {} exited with code {}", c.session, code);
+                }
+                unsafe { CloseHandle(c.process) };
+                let (session_was, lasted) = (c.session, c.started.elapsed());
+                child = None;
+                if !running {
+                    match code as i32 {
+                        // Bad options or config: starting it again would only fail again
+                        1 => {
+                            status!("Service: stopping, as the lock can't start with these options");
+                            return;
+                        }
+                        // Quit from the tray, --once, or another copy already
+                        // locking this session: the user's call, not a crash
+                        0 | EXIT_ALREADY_RUNNING => {
+                            status!("Service: leaving session {} alone until the console moves or its user logs off", session_was);
+                            left_alone = Some(session_was);
+                        }
+                        _ => {
+                            if lasted >= RESPAWN_DELAY_MAX {
+                                respawn_delay = RESPAWN_DELAY_MIN;
+                            }
+                            status!("Service: starting the lock again in {}s", respawn_delay.as_secs());
+                            respawn_at = Some(Instant::now() + respawn_delay);
+                            respawn_delay = (respawn_delay * 2).min(RESPAWN_DELAY_MAX);
+                        }
+                    }
+                }
+            }
+        }
+        let backing_off = respawn_at.is_some_and(|at| Instant::now() < at);
+        if child.is_none() && session != NO_CONSOLE_SESSION && left_alone != Some(session) && !backing_off {
+            respawn_at = None;
+            let started = match user_token(session) {
+                Some(token) => Some((spawn_as(token, command_line, USER_DESKTOP), true)),
+                None if LOGON_SCREEN.load(Ordering::SeqCst) => Some((spawn_on_logon_screen(command_line, session), false)),
+                None => {
+                    if waiting_in != Some(session) {
+                        status!("Service: nobody is logged in to session {}; waiting", session);
+                        waiting_in = Some(session);
+                    }
+                    None
+                }
+            };
+            match started {
+                Some((Ok(process), as_user)) => {
+                    let whom = if as_user { "as the logged-in user" } else { "on the sign-in screen" };
+                    status!("Service: started the lock in session {} {}", session, whom);
+                    child = Some(Child { process, session, as_user, started: Instant::now() });
+                    waiting_in = None;
+                }
+                Some((Err(code), _)) => status!("Service: couldn't start the lock in session {} (error {})", session, code),
+                None => {}
+            }
+        }
+        if stop.is_null() {
+            thread::sleep(Duration::from_millis(SUPERVISE_INTERVAL_MS as u64));
+        } else if unsafe { WaitForSingleObject(stop, SUPERVISE_INTERVAL_MS) } == WAIT_OBJECT_0 {
+            break;
+        }
+    }
+    if let Some(c) = child {
+        let event_name = wide(STOP_EVENT_NAME);
+        unsafe {
+            let event = OpenEventW(EVENT_MODIFY_STATE, FALSE, event_name.as_ptr());
+            if !event.is_null() {
+                SetEvent(event);
+                CloseHandle(event);
+            }
+            if WaitForSingleObject(c.process, CHILD_EXIT_TIMEOUT_MS) != WAIT_OBJECT_0 {
+                status!("Service: the lock didn't exit in time; ending it");
+                TerminateProcess(c.process, 0);
+            }
+            CloseHandle(c.process);
+        }
+    }
+}
+
+/// The primary token of the user logged in to `session`, if anyone is.
+fn user_token(session: DWORD) -> Option<HANDLE> {
+    let mut token: HANDLE = ptr::null_mut();
+    (unsafe { WTSQueryUserToken(session, &mut token) } != 0).then_some(token)
+}
+
+/// Starts `command_line` on the sign-in screen of `session`, as this
+/// process's own account: nobody's logged in to run it as.
+fn spawn_on_logon_screen(command_line: &[u16], session: DWORD) -> Result<HANDLE, u32> {
+    unsafe {
+        let mut own: HANDLE = ptr::null_mut();
+        let access = TOKEN_DUPLICATE | TOKEN_QUERY | TOKEN_ASSIGN_PRIMARY | TOKEN_ADJUST_DEFAULT | TOKEN_ADJUST_SESSIONID;
+        if OpenProcessToken(GetCurrentProcess(), access, &mut own) == 0 {
+            return Err(GetLastError());
+        }
+        let mut token: HANDLE = ptr::null_mut();
+        let duplicated = DuplicateTokenEx(own, MAXIMUM_ALLOWED, ptr::null_mut(), SecurityImpersonation, TokenPrimary, &mut token);
+        CloseHandle(own);
+        if duplicated == 0 {
+            return Err(GetLastError());
+        }
+        let mut session_id = session;
+        let size = std::mem::size_of::<DWORD>() as DWORD;
+        if SetTokenInformation(token, TokenSessionId, &mut session_id as *mut DWORD as LPVOID, size) == 0 {
+            let code = GetLastError();
+            CloseHandle(token);
+            return Err(code);
+        }
+        spawn_as(token, command_line, LOGON_DESKTOP)
+    }
+}
+
+/// Starts `command_line` with `token` on `desktop`, closing the token, and
+/// returns the process handle. The copy gets the token's user's own
+/// environment, plus the name of the stop event.
+fn spawn_as(token: HANDLE, command_line: &[u16], desktop: &str) -> Result<HANDLE, u32> {
+    unsafe {
+        let mut block: LPVOID = ptr::null_mut();
+        let mut environment = Vec::new();
+        if CreateEnvironmentBlock(&mut block, token, FALSE) != 0 {
+            environment = environment_with_stop_event(block as *const u16);
+            DestroyEnvironmentBlock(block);
+        }
+        let mut desktop = wide(desktop);
+        let mut si: STARTUPINFOW = std::mem::zeroed();
+        si.cb = std::mem::size_of::<STARTUPINFOW>() as DWORD;
+        si.lpDesktop = desktop.as_mut_ptr();
+        let mut pi: PROCESS_INFORMATION = std::mem::zeroed();
+        // CreateProcessW may write to the command line, so it gets a copy
+        let mut command_line = command_line.to_vec();
+        // Without a block of its own the copy inherits the service's
+        // environment, stop event included
+        let env_ptr = if environment.is_empty() { ptr::null_mut() } else { environment.as_mut_ptr() as LPVOID };
+        let created = CreateProcessAsUserW(
+            token,
+            ptr::null(),
+            command_line.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            FALSE,
+            CREATE_NO_WINDOW | CREATE_UNICODE_ENVIRONMENT,
+            env_ptr,
+            ptr::null(),
+            &mut si,
+            &mut pi,
+        );
+        let code = GetLastError();
+        CloseHandle(token);
+        if created == 0 {
+            return Err(code);
+        }
+        CloseHandle(pi.hThread);
+        Ok(pi.hProcess)
+    }
+}
+
+/// Copies an environment block (`NAME=value` strings, each ending in a nul,
+/// and a nul after the last) with [`STOP_EVENT_ENV`] added.
+unsafe fn environment_with_stop_event(block: *const u16) -> Vec<u16> {
+    let mut environment = Vec::new();
+    let mut p = block;
+    while *p != 0 {
+        while *p != 0 {
+            environment.push(*p);
+            p = p.add(1);
+        }
+        environment.push(0);
+        p = p.add(1);
+    }
+    environment.extend(wide(&format!("{}={}", STOP_EVENT_ENV, STOP_EVENT_NAME)));
+    environment.push(0);
+    environment
+}
+
+/// In a copy started by the service: calls `on_stop` once the service asks
+/// it to exit. Does nothing outside the service.
+pub fn watch_stop_event(on_stop: impl FnOnce() + Send + 'static) {
+    let Ok(name) = env::var(STOP_EVENT_ENV) else { return };
+    let name = wide(&name);
+    let event = unsafe { OpenEventW(SYNCHRONIZE, FALSE, name.as_ptr()) };
+    if event.is_null() {
+        status!("Couldn't open the service's stop event (error {})", unsafe { GetLastError() });
+        return;
+    }
+    // The handle is only ever waited on, so it travels to the thread as a number
+    let event = event as usize;
+    thread::spawn(move || {
+        if unsafe { WaitForSingleObject(event as HANDLE, INFINITE) } == WAIT_OBJECT_0 {
+            on_stop();
+        }
+    });
+}
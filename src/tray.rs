@@ -0,0 +1,139 @@
+//! Notification area icon with a right-click menu.
+
+use std::{mem, ptr};
+use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::{HWND, POINT};
+use winapi::um::shellapi::{
+    Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO, NIIF_NOSOUND, NIM_ADD, NIM_DELETE,
+    NIM_MODIFY, NOTIFYICONDATAW,
+};
+use winapi::um::winuser::{
+    AppendMenuW, CreatePopupMenu, DestroyMenu, GetCursorPos, LoadIconW, PostMessageW, SetForegroundWindow,
+    TrackPopupMenu, IDI_APPLICATION, MF_CHECKED, MF_SEPARATOR, MF_STRING, TPM_NONOTIFY, TPM_RETURNCMD,
+    TPM_RIGHTBUTTON, WM_APP, WM_NULL,
+};
+
+/// Sent to the owning window for mouse activity on the icon; `lParam` is the
+/// mouse message.
+pub const WM_APP_TRAY: UINT = WM_APP + 2;
+
+const TRAY_ICON_ID: UINT = 1;
+
+pub enum MenuItem {
+    Entry { id: u32, label: String, checked: bool },
+    Separator,
+}
+
+/// The icon is removed again when this is dropped.
+pub struct Tray {
+    hwnd: HWND,
+    tooltip: String,
+}
+
+fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+impl Tray {
+    fn icon_data(&self) -> NOTIFYICONDATAW {
+        let mut data: NOTIFYICONDATAW = unsafe { mem::zeroed() };
+        data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = self.hwnd;
+        data.uID = TRAY_ICON_ID;
+        data
+    }
+
+    /// Adds the icon, reporting clicks to `hwnd` as [`WM_APP_TRAY`].
+    pub fn add(hwnd: HWND, tooltip: &str) -> Option<Tray> {
+        let mut tray = Tray { hwnd, tooltip: String::new() };
+        let mut data = tray.icon_data();
+        data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+        data.uCallbackMessage = WM_APP_TRAY;
+        data.hIcon = unsafe { LoadIconW(ptr::null_mut(), IDI_APPLICATION) };
+        tray.fill_tooltip(&mut data, tooltip);
+        if unsafe { Shell_NotifyIconW(NIM_ADD, &mut data) } == 0 {
+            return None;
+        }
+        Some(tray)
+    }
+
+    /// Copies `text` into a fixed-size, null-terminated buffer, truncating it
+    /// to fit.
+    fn fill(buffer: &mut [u16], text: &str) {
+        let wide: Vec<u16> = text.encode_utf16().take(buffer.len() - 1).collect();
+        buffer[..wide.len()].copy_from_slice(&wide);
+        buffer[wide.len()] = 0;
+    }
+
+    fn fill_tooltip(&mut self, data: &mut NOTIFYICONDATAW, tooltip: &str) {
+        Self::fill(&mut data.szTip, tooltip);
+        self.tooltip = tooltip.to_string();
+    }
+
+    /// Updates the hover text; cheap to call when nothing changed.
+    pub fn set_tooltip(&mut self, tooltip: &str) {
+        if tooltip == self.tooltip {
+            return;
+        }
+        let mut data = self.icon_data();
+        data.uFlags = NIF_TIP;
+        self.fill_tooltip(&mut data, tooltip);
+        unsafe { Shell_NotifyIconW(NIM_MODIFY, &mut data) };
+    }
+
+    /// Pops up a notification from the icon. Windows 10 and later show it as a
+    /// toast, which stays visible over borderless fullscreen games.
+    pub fn notify(&self, title: &str, body: &str) {
+        let mut data = self.icon_data();
+        data.uFlags = NIF_INFO;
+        data.dwInfoFlags = NIIF_INFO | NIIF_NOSOUND;
+        Self::fill(&mut data.szInfoTitle, title);
+        Self::fill(&mut data.szInfo, body);
+        unsafe { Shell_NotifyIconW(NIM_MODIFY, &mut data) };
+    }
+
+    /// Shows `items` at the cursor and waits for a pick. Returns the chosen id,
+    /// or `None` if the menu was dismissed.
+    pub fn show_menu(&self, items: &[MenuItem]) -> Option<u32> {
+        unsafe {
+            let menu = CreatePopupMenu();
+            if menu.is_null() {
+                return None;
+            }
+            for item in items {
+                match item {
+                    MenuItem::Entry { id, label, checked } => {
+                        let flags = if *checked { MF_STRING | MF_CHECKED } else { MF_STRING };
+                        AppendMenuW(menu, flags, *id as usize, to_wide(label).as_ptr());
+                    }
+                    MenuItem::Separator => {
+                        AppendMenuW(menu, MF_SEPARATOR, 0, ptr::null());
+                    }
+                }
+            }
+            let mut pt: POINT = mem::zeroed();
+            GetCursorPos(&mut pt);
+            // Without this the menu doesn't close when clicking elsewhere
+            SetForegroundWindow(self.hwnd);
+            let picked = TrackPopupMenu(
+                menu,
+                TPM_RETURNCMD | TPM_RIGHTBUTTON | TPM_NONOTIFY,
+                pt.x,
+                pt.y,
+                0,
+                self.hwnd,
+                ptr::null(),
+            );
+            PostMessageW(self.hwnd, WM_NULL, 0, 0);
+            DestroyMenu(menu);
+            if picked == 0 { None } else { Some(picked as u32) }
+        }
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        let mut data = self.icon_data();
+        unsafe { Shell_NotifyIconW(NIM_DELETE, &mut data) };
+    }
+}
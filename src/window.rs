@@ -0,0 +1,54 @@
+//! Lookups on other programs' windows.
+
+use std::path::Path;
+use winapi::shared::windef::HWND;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+use winapi::um::winuser::{GetClassNameW, GetWindowThreadProcessId};
+
+pub fn class_name(hwnd: HWND) -> Option<String> {
+    // Class names are limited to 256 characters
+    let mut class = [0u16; 257];
+    let len = unsafe { GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32) };
+    (len > 0).then(|| String::from_utf16_lossy(&class[..len as usize]))
+}
+
+/// File name of the executable that owns `hwnd`, like `explorer.exe`.
+pub fn process_name(hwnd: HWND) -> Option<String> {
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+    if pid == 0 {
+        return None;
+    }
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if process.is_null() {
+        return None;
+    }
+    let mut path = [0u16; 1024];
+    let mut len = path.len() as u32;
+    let ok = unsafe { QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut len) } != 0;
+    unsafe { CloseHandle(process) };
+    if !ok {
+        return None;
+    }
+    let path = String::from_utf16_lossy(&path[..len as usize]);
+    Path::new(&path).file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Whether `hwnd` belongs to one of `names`, matched case-insensitively
+/// against its process's file name and its window class.
+pub fn matches_any(hwnd: HWND, names: &[String]) -> bool {
+    let process = process_name(hwnd);
+    let class = class_name(hwnd);
+    names.iter().any(|name| {
+        process.as_ref().is_some_and(|p| p.eq_ignore_ascii_case(name))
+            || class.as_ref().is_some_and(|c| c.eq_ignore_ascii_case(name))
+    })
+}
+
+/// The `WorkerW` windows that host the desktop icons and wallpaper.
+pub fn is_desktop_window(hwnd: HWND) -> bool {
+    class_name(hwnd).is_some_and(|class| class == "WorkerW")
+}